@@ -0,0 +1,180 @@
+use anyhow::Result;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::yomichan::{DictIndex, MAX_TERM_PER_BANK};
+
+/// A Yomichan dictionary archive being written.
+/// Rows are streamed into `<bank>_bank_N.json` files and `index.json` is added last by `finish`.
+/// A new bank file is started every `MAX_TERM_PER_BANK` rows, or when rows of another kind of bank
+/// (`term`, `term_meta`, `tag`...) come in, so callers should write one kind at a time.
+pub struct DictArchive<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    bank: Option<&'static str>,
+    bank_counts: HashMap<&'static str, u16>,
+    row_count: u16,
+}
+
+impl DictArchive<File> {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(DictArchive::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> DictArchive<W> {
+    pub fn new(writer: W) -> Self {
+        DictArchive {
+            zip: ZipWriter::new(writer),
+            bank: None,
+            bank_counts: HashMap::new(),
+            row_count: 0,
+        }
+    }
+
+    // fixed timestamp and permissions so the same input always gives a byte-identical archive
+    fn options() -> FileOptions {
+        FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644)
+    }
+
+    fn close_bank(&mut self) -> Result<()> {
+        if self.bank.take().is_some() {
+            write!(self.zip, "]")?;
+        }
+        self.row_count = 0;
+        Ok(())
+    }
+
+    pub fn write_row(&mut self, bank: &'static str, row: &str) -> Result<()> {
+        if self.bank != Some(bank) || self.row_count == MAX_TERM_PER_BANK {
+            self.close_bank()?;
+            let bank_count = self.bank_counts.entry(bank).or_insert(0);
+            *bank_count += 1;
+            self.zip.start_file(
+                format!("{}_bank_{}.json", bank, bank_count),
+                Self::options(),
+            )?;
+            write!(self.zip, "[")?;
+            self.bank = Some(bank);
+        } else {
+            writeln!(self.zip, ",")?;
+        }
+        write!(self.zip, "{}", row)?;
+        self.row_count += 1;
+        Ok(())
+    }
+
+    // number of bank files written so far for a kind of bank
+    pub fn bank_count(&self, bank: &str) -> u16 {
+        self.bank_counts.get(bank).copied().unwrap_or(0)
+    }
+
+    pub fn finish(mut self, index: &DictIndex) -> Result<W> {
+        self.close_bank()?;
+        self.zip.start_file("index.json", Self::options())?;
+        write!(self.zip, "{}", index.serialize())?;
+        Ok(self.zip.finish()?)
+    }
+}
+
+/// Path of the archive of the dictionary `name` inside the output directory.
+/// A published dictionary gets a directory of its own (`<name>/<name>.zip` next to `<name>/index.json`)
+/// so the output directory can be served as is by a static web server.
+pub fn archive_path(output_dir: &Path, name: &str, published: bool) -> Result<PathBuf> {
+    if published {
+        let dictionary_dir = output_dir.join(name);
+        std::fs::create_dir_all(&dictionary_dir)?;
+        Ok(dictionary_dir.join(format!("{}.zip", name)))
+    } else {
+        Ok(output_dir.join(format!("{}.zip", name)))
+    }
+}
+
+// the copy of `index.json` Yomichan downloads from `indexUrl` to check for a newer revision
+pub fn write_published_index(output_dir: &Path, name: &str, index: &DictIndex) -> Result<()> {
+    std::fs::write(output_dir.join(name).join("index.json"), index.serialize())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    #[test]
+    fn write_rows_to_banks() {
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        for _ in 0..MAX_TERM_PER_BANK + 1 {
+            archive
+                .write_row("term_meta", r#"["明白","freq",1]"#)
+                .unwrap();
+        }
+        archive.write_row("tag", r#"["uk","",0,"",0]"#).unwrap();
+        assert_eq!(archive.bank_count("term_meta"), 2);
+        let writer = archive.finish(&DictIndex::new("JMdict", "1")).unwrap();
+
+        let mut zip = ZipArchive::new(writer).unwrap();
+        let names = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            names,
+            [
+                "term_meta_bank_1.json",
+                "term_meta_bank_2.json",
+                "tag_bank_1.json",
+                "index.json"
+            ]
+        );
+
+        let mut second_bank = String::new();
+        zip.by_name("term_meta_bank_2.json")
+            .unwrap()
+            .read_to_string(&mut second_bank)
+            .unwrap();
+        assert_eq!(second_bank, r#"[["明白","freq",1]]"#);
+    }
+
+    fn build_archive() -> Vec<u8> {
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        archive
+            .write_row(
+                "term",
+                r#"["明白","めいはく","","",98,["obvious"],1000220,""]"#,
+            )
+            .unwrap();
+        archive
+            .finish(&DictIndex::new("JMdict", "20220207"))
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn reproducible_archive() {
+        let first = build_archive();
+        assert_eq!(first, build_archive());
+
+        // the metadata of every file is fixed, whatever the time of the build
+        let mut zip = ZipArchive::new(Cursor::new(first)).unwrap();
+        for i in 0..zip.len() {
+            let file = zip.by_index(i).unwrap();
+            assert_eq!(
+                file.last_modified().datepart(),
+                DateTime::default().datepart()
+            );
+            assert_eq!(
+                file.last_modified().timepart(),
+                DateTime::default().timepart()
+            );
+            assert_eq!(file.unix_mode(), Some(0o100644));
+        }
+    }
+}
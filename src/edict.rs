@@ -0,0 +1,258 @@
+use anyhow::{anyhow, Result};
+
+use std::io::{BufRead, Lines};
+
+use crate::entry::{Entry, Form, Sense, COMMON_PRIORITY};
+use crate::source::DictionarySource;
+
+// the <misc> entities of the JMdict DTD, every other code in parentheses is a part of speech
+const MISC_CODES: &str = "abbr arch char chn col company creat dated dei derog doc euph ev fam fem fict \
+    form given group hist hon hum id joc leg m-sl male male-sl myth net-sl obj obs obsc on-mim organization \
+    oth person place poet pol product proverb quote rare relig sens serv ship sl station surname uk unclass \
+    vulg work X yoji";
+
+/// Reads the entries of an EDICT2 file, converted to UTF-8, one line per entry:
+/// `KANJI(P);KANJI [KANA(P);KANA(KANJI)] /(pos) (1) gloss/gloss/(2) gloss/(P)/EntL1000220X/`
+///
+/// EDICT2 only marks forms as common with `(P)`, so their priority codes become `COMMON_PRIORITY`,
+/// and it has no `re_nokanji` flag.
+pub struct EdictReader<R: BufRead> {
+    lines: Lines<R>,
+    line_number: usize,
+}
+
+impl<R: BufRead> EdictReader<R> {
+    pub fn new(source: R) -> Self {
+        EdictReader {
+            lines: source.lines(),
+            line_number: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EdictReader<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line_number += 1;
+            // the first line is the `？？？？ /EDICT, ...` header
+            if line.trim().is_empty() || line.starts_with("？？？？") {
+                continue;
+            }
+            return Some(
+                parse_line(&line)
+                    .map_err(|e| anyhow!("Invalid EDICT2 line {}: {}", self.line_number, e)),
+            );
+        }
+    }
+}
+
+impl<R: BufRead> DictionarySource for EdictReader<R> {}
+
+// the `/Created: YYYY-MM-DD/` field of the header line
+pub fn edict_created(edict: &str) -> Option<String> {
+    let header = edict.lines().next()?;
+    let created_re = regex::Regex::new(r"/Created: (\d{4}-\d{2}-\d{2})/")
+        .expect("Could not compile EDICT created regex");
+    created_re.captures(header).map(|cap| cap[1].to_string())
+}
+
+fn parse_line(line: &str) -> Result<Entry> {
+    let (headwords, glosses) = line.split_once(" /").ok_or_else(|| anyhow!("no glosses"))?;
+    let mut entry = Entry::default();
+    match headwords.split_once(" [") {
+        Some((kanji, readings)) => {
+            let readings = readings
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("unclosed readings"))?;
+            entry.kanji = split_forms(kanji).map(parse_form).collect();
+            entry.readings = split_forms(readings).map(parse_form).collect();
+        }
+        // a kana only word is written as its reading
+        None => entry.readings = split_forms(headwords).map(parse_form).collect(),
+    }
+
+    for field in glosses.trim_end().trim_end_matches('/').split('/') {
+        if let Some(ent_seq) = field.strip_prefix("EntL") {
+            // `X` marks the entries having a recording
+            let ent_seq = ent_seq.trim_end_matches('X');
+            entry.ent_seq = ent_seq
+                .parse()
+                .map_err(|_| anyhow!("invalid ent_seq `{}`", ent_seq))?;
+        } else if field != "(P)" {
+            // `(P)` alone marks the entry as common, its forms are marked on their own
+            parse_gloss(field, &mut entry);
+        }
+    }
+    if entry.ent_seq == 0 {
+        return Err(anyhow!("no EntL sequence number"));
+    }
+    Ok(entry)
+}
+
+// `;` separates the forms, and the restrictions of a reading inside its parentheses
+fn split_forms(forms: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    forms
+        .split(move |c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => (),
+            }
+            c == ';' && depth == 0
+        })
+        .filter(|form| !form.is_empty())
+}
+
+// `めいはく(P)(ok)` or `かっぱ(合羽)`, codes in ASCII and restricted kanji forms in Japanese
+fn parse_form(text: &str) -> Form {
+    let mut form = Form::new(text.to_string());
+    while let Some(open) = form.text.strip_suffix(')').and_then(|text| text.rfind('(')) {
+        let group = form.text[open + 1..form.text.len() - 1].to_string();
+        form.text.truncate(open);
+        if group.is_ascii() {
+            for code in group.split(',') {
+                match code {
+                    "P" => form.priority.insert(0, COMMON_PRIORITY.to_string()),
+                    code => form.info.insert(0, code.to_string()),
+                }
+            }
+        } else {
+            form.restrictions = group.split(';').map(str::to_string).collect();
+        }
+    }
+    form
+}
+
+// one `/.../` field, the codes in parentheses before its text
+// belong to the sense started by `(N)`, or to the current one
+fn parse_gloss(field: &str, entry: &mut Entry) {
+    let mut sense = Sense::default();
+    let mut new_sense = entry.senses.is_empty();
+    let mut text = field.trim();
+    loop {
+        if let Some(rest) = text.strip_prefix('{') {
+            // `{comp}` field codes
+            let Some((group, rest)) = rest.split_once('}') else {
+                break;
+            };
+            sense.field.extend(group.split(',').map(str::to_string));
+            text = rest.trim_start();
+            continue;
+        }
+        let Some((group, rest)) = text.strip_prefix('(').and_then(|rest| rest.split_once(')'))
+        else {
+            break;
+        };
+        if group.parse::<u32>().is_ok() {
+            new_sense = true;
+        } else if let Some(forms) = group.strip_suffix(" only") {
+            // `(遇う only)`, a sense restricted to some of the forms
+            for form in forms.split([',', ';']).map(str::trim) {
+                if entry.kanji.iter().any(|kanji| kanji.text == form) {
+                    sense.kanji_restrictions.push(form.to_string());
+                } else {
+                    sense.reading_restrictions.push(form.to_string());
+                }
+            }
+        } else if !group.is_empty()
+            && group
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | ':' | ','))
+        {
+            for code in group.split(',') {
+                if let Some(dialect) = code.strip_suffix(':') {
+                    sense.dialect.push(dialect.to_string());
+                } else if MISC_CODES.split_whitespace().any(|misc| misc == code) {
+                    sense.misc.push(code.to_string());
+                } else {
+                    sense.pos.push(code.to_string());
+                }
+            }
+        } else {
+            // `(See 明白)` or `(Pieris japonica)` are part of the text
+            break;
+        }
+        text = rest.trim_start();
+    }
+
+    if new_sense {
+        entry.senses.push(Sense::default());
+    }
+    let current = entry.senses.last_mut().expect("a sense was just added");
+    current.kanji_restrictions.extend(sense.kanji_restrictions);
+    current
+        .reading_restrictions
+        .extend(sense.reading_restrictions);
+    current.pos.extend(sense.pos);
+    current.field.extend(sense.field);
+    current.misc.extend(sense.misc);
+    current.dialect.extend(sense.dialect);
+    if !text.is_empty() {
+        current.gloss.push(text.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_edict_sample() {
+        let edict = std::fs::read_to_string("tests/edict2-sample.txt").unwrap();
+        assert_eq!(edict_created(&edict), Some("2022-02-07".to_string()));
+        let entries = EdictReader::new(edict.as_bytes())
+            .collect::<Result<Vec<Entry>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let meihaku = &entries[0];
+        assert_eq!(meihaku.ent_seq, 1000220);
+        assert_eq!(meihaku.kanji[0].text, "明白");
+        assert_eq!(meihaku.kanji[0].priority, [COMMON_PRIORITY]);
+        assert_eq!(meihaku.senses.len(), 1);
+        assert_eq!(meihaku.senses[0].pos, ["adj-na"]);
+        assert_eq!(meihaku.senses[0].gloss.len(), 7);
+
+        let ashirau = &entries[1];
+        assert_eq!(ashirau.senses.len(), 2);
+        assert_eq!(ashirau.senses[0].pos, ["v5u", "vt"]);
+        assert_eq!(ashirau.senses[0].misc, ["uk"]);
+        assert_eq!(ashirau.senses[0].kanji_restrictions, ["遇う"]);
+        assert_eq!(
+            ashirau.senses[0].gloss,
+            ["to treat", "to handle", "to deal with"]
+        );
+        assert_eq!(ashirau.senses[1].gloss[0], "to arrange");
+
+        // the scientific name stays in the gloss
+        assert_eq!(
+            entries[2].senses[0].gloss[0],
+            "Japanese andromeda (Pieris japonica)"
+        );
+
+        let kappa = &entries[3];
+        assert_eq!(kappa.kanji[0].info, ["ateji"]);
+        assert_eq!(kappa.readings[0].text, "かっぱ");
+        assert_eq!(kappa.readings[0].restrictions, ["合羽"]);
+        assert_eq!(kappa.readings[0].priority, [COMMON_PRIORITY]);
+        assert_eq!(kappa.readings[1].restrictions, ["雨合羽"]);
+        assert_eq!(kappa.senses[0].field, ["cloth"]);
+        assert_eq!(kappa.senses[1].dialect, ["ksb"]);
+    }
+
+    #[test]
+    fn read_broken_edict() {
+        // no sequence number
+        let mut reader = EdictReader::new("明白 [めいはく] /(adj-na) obvious/\n".as_bytes());
+        assert!(reader.next().unwrap().is_err());
+        let mut reader = EdictReader::new("明白 [めいはく]\n".as_bytes());
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// The priority code of a form the source only marks as common,
+/// e.g. `(P)` in EDICT2 or `"common": true` in jmdict-simplified.
+/// It is no JMdict code, `ScoreWeights` gives it a weight of its own.
+pub const COMMON_PRIORITY: &str = "common";
+
+// the priority codes of the forms jmdict-simplified and the EDICT2 `(P)` count as common
+const COMMON_PRIORITIES: [&str; 6] = ["news1", "ichi1", "spec1", "spec2", "gai1", COMMON_PRIORITY];
+
+/// One `<entry>` of JMdict or JMnedict, as read by `JmdictReader`.
+/// The DTD entities are kept as their names, e.g. `uk` or `v5u`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub ent_seq: u32,
+    // <k_ele>
+    #[serde(default)]
+    pub kanji: Vec<Form>,
+    // <r_ele>
+    #[serde(default)]
+    pub readings: Vec<Form>,
+    // <sense>, or <trans> in JMnedict
+    #[serde(default)]
+    pub senses: Vec<Sense>,
+}
+
+/// A kanji form (`<keb>`) or a reading (`<reb>`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Form {
+    pub text: String,
+    // <ke_inf>/<re_inf>
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub info: Vec<String>,
+    // <ke_pri>/<re_pri>
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub priority: Vec<String>,
+    // <re_restr>, the only kanji forms a reading reads
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restrictions: Vec<String>,
+    // <re_nokanji/>, a reading of none of the kanji forms
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_kanji: bool,
+}
+
+impl Form {
+    pub fn new(text: String) -> Self {
+        Form {
+            text,
+            ..Form::default()
+        }
+    }
+
+    pub fn is_common(&self) -> bool {
+        self.priority
+            .iter()
+            .any(|priority| COMMON_PRIORITIES.contains(&priority.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sense {
+    // <stagk>/<stagr>, the only forms the sense applies to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kanji_restrictions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reading_restrictions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pos: Vec<String>,
+    // <xref> and <ant>, e.g. `明白・めいはく・1`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub xrefs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub antonyms: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub field: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub misc: Vec<String>,
+    // <s_inf>
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub info: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dialect: Vec<String>,
+    // <name_type>, only in JMnedict
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_type: Vec<String>,
+    // <gloss>, or <trans_det> in JMnedict
+    #[serde(default)]
+    pub gloss: Vec<String>,
+    // <example>, only in JMdict_e_examp
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+}
+
+/// One `<example>` of a sense.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    // <ex_text>, the form of the term used in the sentence
+    pub text: String,
+    // <ex_sent xml:lang="jpn">
+    pub sentence: String,
+    // <ex_sent xml:lang="eng">
+    pub translation: String,
+}
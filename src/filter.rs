@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Context, Result};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1};
+use nom::character::complete::{alpha1, char, multispace0, multispace1};
+use nom::combinator::{all_consuming, map, map_opt, map_res, peek};
+use nom::multi::separated_list1;
+use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+use nom::IResult;
+use regex::Regex;
+
+use std::collections::HashSet;
+
+use crate::entry::Entry;
+
+/// Keys of the `key:pattern` tests, see `codes`.
+const KEYS: [&str; 5] = ["pri", "inf", "pos", "misc", "field"];
+
+/// Which entries go into an edition, e.g. `(pri:news1 or pri:ichi1) and not misc:vulg`.
+/// `not` binds tighter than `and`, which binds tighter than `or`,
+/// and `*` in a pattern matches any run of characters (`pos:v5*`).
+#[derive(Debug)]
+pub enum Filter {
+    // one of the codes of the key matches the pattern
+    Code(String, Regex),
+    // the ent_seq is one of the numbers of a file
+    SeqIn(String, HashSet<u32>),
+    Not(Box<Filter>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let (_, mut filter) = all_consuming(delimited(multispace0, or, multispace0))(expression)
+            .map_err(|e| match e {
+                nom::Err::Error(e) | nom::Err::Failure(e) if !e.input.is_empty() => {
+                    anyhow!("Could not parse filter `{}` at `{}`", expression, e.input)
+                }
+                _ => anyhow!("Could not parse filter `{}`, it ends too early", expression),
+            })?;
+        filter.read_seq_files()?;
+        Ok(filter)
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Filter::Code(key, pattern) => codes(entry, key)
+                .into_iter()
+                .any(|code| pattern.is_match(code)),
+            Filter::SeqIn(_, sequences) => sequences.contains(&entry.ent_seq),
+            Filter::Not(filter) => !filter.matches(entry),
+            Filter::And(filters) => filters.iter().all(|x| x.matches(entry)),
+            Filter::Or(filters) => filters.iter().any(|x| x.matches(entry)),
+        }
+    }
+
+    fn read_seq_files(&mut self) -> Result<()> {
+        match self {
+            Filter::Code(..) => (),
+            Filter::SeqIn(path, sequences) => *sequences = read_seq_file(path)?,
+            Filter::Not(filter) => filter.read_seq_files()?,
+            Filter::And(filters) | Filter::Or(filters) => {
+                for filter in filters {
+                    filter.read_seq_files()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// the codes a filter tests: `pri` and `inf` of every form, `pos`, `misc` and `field` of every sense
+fn codes<'a>(entry: &'a Entry, key: &str) -> Vec<&'a str> {
+    let forms = entry.kanji.iter().chain(&entry.readings);
+    let codes: Vec<&Vec<String>> = match key {
+        "pri" => forms.map(|form| &form.priority).collect(),
+        "inf" => forms.map(|form| &form.info).collect(),
+        "pos" => entry.senses.iter().map(|sense| &sense.pos).collect(),
+        "misc" => entry.senses.iter().map(|sense| &sense.misc).collect(),
+        "field" => entry.senses.iter().map(|sense| &sense.field).collect(),
+        _ => Vec::new(),
+    };
+    codes.into_iter().flatten().map(String::as_str).collect()
+}
+
+// ent_seq numbers separated by whitespace, `#` starts a comment
+fn read_seq_file(path: &str) -> Result<HashSet<u32>> {
+    let raw_seq_input = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read the ent_seq file `{}` of the filter", path))?;
+    raw_seq_input
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+        .map(|seq| {
+            seq.parse::<u32>()
+                .with_context(|| format!("Invalid ent_seq `{}` in `{}`", seq, path))
+        })
+        .collect()
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')'
+}
+
+// a word of the language, it must not run into the next word (`order` is not `or`)
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    delimited(
+        multispace0,
+        tag(word),
+        peek(alt((multispace1, tag("("), tag(")")))),
+    )
+}
+
+fn or(input: &str) -> IResult<&str, Filter> {
+    map(
+        separated_list1(keyword("or"), and),
+        |mut filters| match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::Or(filters),
+        },
+    )(input)
+}
+
+fn and(input: &str) -> IResult<&str, Filter> {
+    map(
+        separated_list1(keyword("and"), not),
+        |mut filters| match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::And(filters),
+        },
+    )(input)
+}
+
+fn not(input: &str) -> IResult<&str, Filter> {
+    alt((
+        map(preceded(keyword("not"), not), |filter| {
+            Filter::Not(Box::new(filter))
+        }),
+        delimited(
+            preceded(multispace0, char('(')),
+            or,
+            preceded(multispace0, char(')')),
+        ),
+        seq_in,
+        code,
+    ))(input)
+}
+
+fn seq_in(input: &str) -> IResult<&str, Filter> {
+    map(
+        preceded(
+            tuple((keyword("seq"), keyword("in"), multispace0)),
+            take_till1(is_delimiter),
+        ),
+        |path: &str| Filter::SeqIn(path.to_string(), HashSet::new()),
+    )(input)
+}
+
+fn code(input: &str) -> IResult<&str, Filter> {
+    let key = map_opt(alpha1, |key: &str| KEYS.iter().find(|&&x| x == key));
+    let pattern = map_res(take_till1(is_delimiter), |pattern: &str| {
+        let pattern = regex::escape(pattern).replace(r"\*", ".*");
+        Regex::new(&format!("^{}$", pattern))
+    });
+    map(
+        preceded(
+            multispace0,
+            terminated(separated_pair(key, char(':'), pattern), multispace0),
+        ),
+        |(key, pattern)| Filter::Code(key.to_string(), pattern),
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{Form, Sense};
+
+    fn sample_entry() -> Entry {
+        Entry {
+            ent_seq: 1000220,
+            kanji: vec![Form {
+                priority: vec!["ichi1".to_string()],
+                ..Form::new("明白".to_string())
+            }],
+            readings: vec![Form::new("めいはく".to_string())],
+            senses: vec![
+                Sense {
+                    pos: vec!["v5u".to_string()],
+                    misc: vec!["vulg".to_string()],
+                    ..Sense::default()
+                },
+                Sense {
+                    pos: vec!["n".to_string()],
+                    field: vec!["med".to_string()],
+                    ..Sense::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn match_codes() {
+        let entry = sample_entry();
+        let matches = |expression| Filter::parse(expression).unwrap().matches(&entry);
+        assert!(matches("pri:news1 or pri:ichi1"));
+        assert!(!matches("not misc:vulg"));
+        assert!(matches("pos:v5*"));
+        assert!(!matches("pos:v1*"));
+        assert!(matches("field:med and (pos:n or pos:adj-na)"));
+        assert!(matches("not (field:comp or misc:arch)"));
+        // not binds tighter than and, and tighter than or
+        assert!(matches("pri:news1 and not pos:n or field:med"));
+        assert!(!matches("pri:news1 and (not pos:n or field:med)"));
+    }
+
+    #[test]
+    fn match_seq_file() {
+        let entry = sample_entry();
+        let filter = Filter::parse("seq in tests/seq-sample.txt and not misc:arch").unwrap();
+        assert!(filter.matches(&entry));
+        assert!(!Filter::parse("not seq in tests/seq-sample.txt")
+            .unwrap()
+            .matches(&entry));
+        assert!(Filter::parse("seq in tests/does-not-exist.txt").is_err());
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(Filter::parse("").is_err());
+        assert!(Filter::parse("color:red").is_err());
+        assert!(Filter::parse("pri:news1 or").is_err());
+        assert!(Filter::parse("(pri:news1").is_err());
+        assert!(Filter::parse("pri:news1 pri:ichi1").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{bail, Result};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::entry::Entry;
+
+/// The `ke_inf`/`re_inf` codes a policy can be set for.
+pub const FORM_STATUS_CODES: [&str; 9] = ["sK", "sk", "iK", "ik", "io", "oK", "ok", "rK", "rk"];
+
+/// What becomes of a kanji form or reading marked with a status code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormPolicy {
+    // no row and no mention in the glossary
+    Drop,
+    // a row to look it up, but left out of the headers
+    Hide,
+    // a row with the status penalty of the score and the code as term tag
+    Penalize,
+}
+
+impl FromStr for FormPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "drop" => Ok(FormPolicy::Drop),
+            "hide" => Ok(FormPolicy::Hide),
+            "penalize" => Ok(FormPolicy::Penalize),
+            _ => bail!("unknown form policy `{}`", s),
+        }
+    }
+}
+
+/// Policy of each status code, search-only forms are hidden and the others penalized by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormPolicies {
+    policies: HashMap<&'static str, FormPolicy>,
+}
+
+impl Default for FormPolicies {
+    fn default() -> Self {
+        let policies = FORM_STATUS_CODES
+            .iter()
+            .map(|&code| match code {
+                "sK" | "sk" => (code, FormPolicy::Hide),
+                _ => (code, FormPolicy::Penalize),
+            })
+            .collect();
+        FormPolicies { policies }
+    }
+}
+
+impl FormPolicies {
+    // set the policy of one code from a `code=policy` string given on the command line
+    pub fn set(&mut self, code_policy: &str) -> Option<&mut Self> {
+        let (code, policy) = code_policy.split_once('=')?;
+        let code = FORM_STATUS_CODES.iter().find(|&&x| x == code.trim())?;
+        let policy = policy.trim().parse::<FormPolicy>().ok()?;
+        self.policies.insert(code, policy);
+        Some(self)
+    }
+
+    pub fn policy(&self, code: &str) -> Option<FormPolicy> {
+        self.policies.get(code).copied()
+    }
+
+    // the strictest policy of the codes of a form, `None` when none of them has a policy
+    pub fn form_policy<'a>(
+        &self,
+        info: impl IntoIterator<Item = &'a String>,
+    ) -> Option<FormPolicy> {
+        info.into_iter()
+            .filter_map(|code| self.policy(code))
+            .min_by_key(|policy| match policy {
+                FormPolicy::Drop => 0,
+                FormPolicy::Hide => 1,
+                FormPolicy::Penalize => 2,
+            })
+    }
+
+    /// Removes the forms of `entry` with the drop policy, the same way for every output format.
+    /// Hidden and penalized forms are kept, they only change the Yomichan rows.
+    pub fn drop_forms(&self, entry: &mut Entry) {
+        let (kept_kanji, dropped_kanji) = std::mem::take(&mut entry.kanji)
+            .into_iter()
+            .partition::<Vec<_>, _>(|form| self.form_policy(&form.info) != Some(FormPolicy::Drop));
+        entry.kanji = kept_kanji;
+        entry
+            .readings
+            .retain(|form| self.form_policy(&form.info) != Some(FormPolicy::Drop));
+        // a reading restricted to dropped kanji forms no longer reads any of them
+        for reading in &mut entry.readings {
+            if !reading.restrictions.is_empty() {
+                reading
+                    .restrictions
+                    .retain(|term| !dropped_kanji.iter().any(|form| &form.text == term));
+                reading.no_kanji |= reading.restrictions.is_empty();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Form;
+
+    fn codes(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn set_policy() {
+        let mut policies = FormPolicies::default();
+        assert_eq!(policies.policy("sK"), Some(FormPolicy::Hide));
+        assert_eq!(policies.policy("iK"), Some(FormPolicy::Penalize));
+        assert_eq!(policies.policy("ateji"), None);
+        policies.set("iK=drop").unwrap();
+        policies.set("rk = hide").unwrap();
+        assert_eq!(policies.policy("iK"), Some(FormPolicy::Drop));
+        assert_eq!(policies.policy("rk"), Some(FormPolicy::Hide));
+        assert!(policies.set("ateji=drop").is_none());
+        assert!(policies.set("sK=remove").is_none());
+        assert!(policies.set("sK").is_none());
+    }
+
+    #[test]
+    fn strictest_policy() {
+        let mut policies = FormPolicies::default();
+        policies.set("oK=drop").unwrap();
+        assert_eq!(policies.form_policy(&codes(&["ateji"])), None);
+        assert_eq!(
+            policies.form_policy(&codes(&["iK", "sK"])),
+            Some(FormPolicy::Hide)
+        );
+        assert_eq!(
+            policies.form_policy(&codes(&["iK", "oK", "sK"])),
+            Some(FormPolicy::Drop)
+        );
+    }
+
+    #[test]
+    fn drop_entry_forms() {
+        let mut entry = Entry::default();
+        for (text, info) in [("馬酔木", &[][..]), ("梫", &["rK"][..])] {
+            entry.kanji.push(Form {
+                info: codes(info),
+                ..Form::new(text.to_string())
+            });
+        }
+        for (text, info, restrictions) in [
+            ("あせび", &[][..], &["馬酔木"][..]),
+            ("あしび", &[][..], &["梫"][..]),
+            ("あせぼ", &["ok"][..], &[][..]),
+        ] {
+            entry.readings.push(Form {
+                info: codes(info),
+                restrictions: codes(restrictions),
+                ..Form::new(text.to_string())
+            });
+        }
+        let mut policies = FormPolicies::default();
+        policies.set("rK=drop").unwrap();
+        policies.set("ok=drop").unwrap();
+        policies.drop_forms(&mut entry);
+
+        assert_eq!(entry.kanji, [Form::new("馬酔木".to_string())]);
+        assert_eq!(entry.readings.len(), 2);
+        assert_eq!(entry.readings[0].restrictions, ["馬酔木"]);
+        assert!(!entry.readings[0].no_kanji);
+        // あしび only read the dropped 梫
+        assert!(entry.readings[1].restrictions.is_empty());
+        assert!(entry.readings[1].no_kanji);
+    }
+}
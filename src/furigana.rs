@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+
+/// A run of a term, with the part of the reading written above it when it contains kanji.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    pub ruby: Option<String>,
+}
+
+impl Segment {
+    fn plain(text: &str) -> Self {
+        Segment {
+            text: text.to_string(),
+            ruby: None,
+        }
+    }
+    fn ruby(text: &str, ruby: &str) -> Self {
+        Segment {
+            text: text.to_string(),
+            ruby: Some(ruby.to_string()),
+        }
+    }
+}
+
+/// Furigana of term[reading] pairs, from a JmdictFurigana-style file when one is given
+/// and from the built-in aligner for every pair the file does not know.
+#[derive(Debug, Clone, Default)]
+pub struct Furigana {
+    mapping: HashMap<(String, String), Vec<Segment>>,
+}
+
+// ヶ and ヵ are read like kanji (一ヶ月, いっかげつ)
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{309f}' | '\u{30a0}'..='\u{30ff}') && !matches!(c, 'ヵ' | 'ヶ')
+}
+
+// katakana folded to hiragana so `アセビ` lines up with `あせび`, every char keeps its UTF-8 length
+fn to_hiragana(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'ァ'..='ヴ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// `明白|めいはく|0:めい;1:はく`, ranges are char indices of the term, e.g. `0-1:おとな`
+fn parse_line(line: &str) -> Result<((String, String), Vec<Segment>)> {
+    let fields = line.split('|').collect::<Vec<&str>>();
+    if fields.len() != 3 {
+        return Err(anyhow!("expected `term|reading|furigana`"));
+    }
+    let chars = fields[0].chars().collect::<Vec<char>>();
+    let mut segments = Vec::new();
+    let mut next = 0;
+    for annotation in fields[2].split(';').filter(|x| !x.is_empty()) {
+        let (range, ruby) = annotation
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected `index:ruby` in `{}`", annotation))?;
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>()?, end.parse::<usize>()?),
+            None => (range.parse::<usize>()?, range.parse::<usize>()?),
+        };
+        if start < next || end < start || end >= chars.len() {
+            return Err(anyhow!("invalid range `{}`", range));
+        }
+        if start > next {
+            segments.push(Segment::plain(
+                &chars[next..start].iter().collect::<String>(),
+            ));
+        }
+        segments.push(Segment::ruby(
+            &chars[start..=end].iter().collect::<String>(),
+            ruby,
+        ));
+        next = end + 1;
+    }
+    if next < chars.len() {
+        segments.push(Segment::plain(&chars[next..].iter().collect::<String>()));
+    }
+    Ok(((fields[0].to_string(), fields[1].to_string()), segments))
+}
+
+impl Furigana {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let raw_furigana_input = std::fs::read_to_string(path)?;
+        let mut mapping = HashMap::new();
+        for (i, line) in raw_furigana_input
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+        {
+            let (form, segments) = parse_line(line).with_context(|| {
+                format!("Could not parse line {} of furigana file `{}`", i + 1, path)
+            })?;
+            mapping.insert(form, segments);
+        }
+        Ok(Furigana { mapping })
+    }
+
+    pub fn segments(&self, term: &str, reading: &str) -> Vec<Segment> {
+        match self.mapping.get(&(term.to_string(), reading.to_string())) {
+            Some(segments) => segments.clone(),
+            None => align(term, reading),
+        }
+    }
+
+    /// Term with ruby on its kanji, as structured content.
+    pub fn ruby(&self, term: &str, reading: &str) -> Value {
+        let content = self
+            .segments(term, reading)
+            .into_iter()
+            .map(|segment| match segment.ruby {
+                Some(ruby) => json!({
+                    "tag": "ruby",
+                    "content": [segment.text, {"tag": "rt", "content": ruby}],
+                }),
+                None => json!(segment.text),
+            })
+            .collect::<Vec<Value>>();
+        match content.len() {
+            1 => content.into_iter().next().unwrap(),
+            _ => Value::Array(content),
+        }
+    }
+}
+
+/// Built-in aligner: the kana of the term must appear as is in the reading,
+/// and each block of kanji gets the part of the reading between them.
+/// A term that cannot be aligned gets the whole reading above it.
+pub fn align(term: &str, reading: &str) -> Vec<Segment> {
+    if reading.is_empty() || term == reading || term.chars().all(is_kana) {
+        return vec![Segment::plain(term)];
+    }
+
+    // blocks of kanji and blocks of kana, in order
+    let mut blocks: Vec<(bool, String)> = Vec::new();
+    for c in term.chars() {
+        match blocks.last_mut() {
+            Some((kana, block)) if *kana == is_kana(c) => block.push(c),
+            _ => blocks.push((is_kana(c), c.to_string())),
+        }
+    }
+    let pattern = blocks
+        .iter()
+        .map(|(kana, block)| match kana {
+            true => regex::escape(&to_hiragana(block)),
+            false => "(.+?)".to_string(),
+        })
+        .collect::<String>();
+    let captures = Regex::new(&format!("^{}$", pattern)).ok().and_then(|re| {
+        re.captures(&to_hiragana(reading)).map(|cap| {
+            cap.iter()
+                .skip(1)
+                .flatten()
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<(usize, usize)>>()
+        })
+    });
+
+    match captures {
+        Some(ranges) => {
+            let mut ranges = ranges.into_iter();
+            blocks
+                .iter()
+                .map(|(kana, block)| match kana {
+                    true => Segment::plain(block),
+                    false => {
+                        let (start, end) = ranges.next().expect("one capture per kanji block");
+                        Segment::ruby(block, &reading[start..end])
+                    }
+                })
+                .collect()
+        }
+        None => vec![Segment::ruby(term, reading)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_sample_file() {
+        let furigana = Furigana::from_file("tests/furigana-sample.txt").unwrap();
+        assert_eq!(
+            furigana.segments("明白", "めいはく"),
+            [Segment::ruby("明", "めい"), Segment::ruby("白", "はく")]
+        );
+        assert_eq!(
+            furigana.segments("遇う", "あしらう"),
+            [Segment::ruby("遇", "あしら"), Segment::plain("う")]
+        );
+        assert_eq!(
+            furigana.segments("大人", "おとな"),
+            [Segment::ruby("大人", "おとな")]
+        );
+        assert!(parse_line("明白|めいはく|2:めい").is_err());
+    }
+
+    #[test]
+    fn align_kanji_blocks() {
+        assert_eq!(
+            align("取り扱い", "とりあつかい"),
+            [
+                Segment::ruby("取", "と"),
+                Segment::plain("り"),
+                Segment::ruby("扱", "あつか"),
+                Segment::plain("い")
+            ]
+        );
+        assert_eq!(
+            align("馬酔木", "あせび"),
+            [Segment::ruby("馬酔木", "あせび")]
+        );
+        assert_eq!(
+            align("お茶", "おちゃ"),
+            [Segment::plain("お"), Segment::ruby("茶", "ちゃ")]
+        );
+        // katakana of the term matches hiragana of the reading
+        assert_eq!(
+            align("ツル性", "つるせい"),
+            [Segment::plain("ツル"), Segment::ruby("性", "せい")]
+        );
+        // the kana do not appear in the reading
+        assert_eq!(align("見る", "みた"), [Segment::ruby("見る", "みた")]);
+        assert_eq!(align("あしらう", "あしらう"), [Segment::plain("あしらう")]);
+    }
+
+    #[test]
+    fn ruby_content() {
+        let furigana = Furigana::default();
+        assert_eq!(
+            furigana.ruby("配う", "あしらう"),
+            json!([
+                {"tag": "ruby", "content": ["配", {"tag": "rt", "content": "あしら"}]},
+                "う"
+            ])
+        );
+        assert_eq!(furigana.ruby("あしらう", ""), json!("あしらう"));
+    }
+}
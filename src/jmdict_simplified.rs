@@ -0,0 +1,431 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use crate::entry::{Entry, Form, Sense, COMMON_PRIORITY};
+use crate::source::DictionarySource;
+use crate::writer::DictionaryWriter;
+
+// `appliesToKanji`/`appliesToKana` of a form or sense without restrictions
+const ALL_FORMS: &str = "*";
+
+// the jmdict-simplified release whose schema the documents follow
+pub const SCHEMA_VERSION: &str = "3.1.0";
+
+/// The jmdict-simplified JSON document (https://github.com/scriptin/jmdict-simplified).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Document {
+    pub version: String,
+    pub languages: Vec<String>,
+    pub common_only: bool,
+    pub dict_date: String,
+    pub dict_revisions: Vec<String>,
+    // code to description, e.g. `"uk": "word usually written using kana alone"`
+    pub tags: BTreeMap<String, String>,
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub id: String,
+    pub kanji: Vec<Kanji>,
+    pub kana: Vec<Kana>,
+    pub sense: Vec<WordSense>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Kanji {
+    pub common: bool,
+    pub text: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Kana {
+    pub common: bool,
+    pub text: String,
+    pub tags: Vec<String>,
+    // `["*"]` for every kanji form, `[]` for none of them
+    pub applies_to_kanji: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordSense {
+    pub part_of_speech: Vec<String>,
+    pub applies_to_kanji: Vec<String>,
+    pub applies_to_kana: Vec<String>,
+    pub related: Vec<Xref>,
+    pub antonym: Vec<Xref>,
+    pub field: Vec<String>,
+    pub dialect: Vec<String>,
+    pub misc: Vec<String>,
+    pub info: Vec<String>,
+    pub language_source: Vec<LanguageSource>,
+    pub gloss: Vec<Gloss>,
+}
+
+/// `["明白", "めいはく", 1]`, `["明白", 1]` or `["めいはく"]`.
+pub type Xref = Vec<XrefPart>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum XrefPart {
+    Text(String),
+    Sense(u32),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LanguageSource {
+    pub lang: String,
+    pub full: bool,
+    pub wasei: bool,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Gloss {
+    pub lang: String,
+    pub gender: Option<String>,
+    #[serde(rename = "type")]
+    pub gloss_type: Option<String>,
+    pub text: String,
+}
+
+// the forms an `appliesTo` list restricts to, none when it is `["*"]`
+fn restrictions(applies_to: &[String]) -> Vec<String> {
+    match applies_to {
+        [all] if all == ALL_FORMS => Vec::new(),
+        forms => forms.to_vec(),
+    }
+}
+
+// the `appliesTo` list of some restrictions, `["*"]` when there are none
+fn applies_to(restrictions: &[String]) -> Vec<String> {
+    match restrictions.is_empty() {
+        true => vec![ALL_FORMS.to_string()],
+        false => restrictions.to_vec(),
+    }
+}
+
+// `明白・めいはく・1`, the way JMdict writes an <xref>
+fn xref_text(xref: &Xref) -> String {
+    xref.iter()
+        .map(|part| match part {
+            XrefPart::Text(text) => text.clone(),
+            XrefPart::Sense(sense) => sense.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("・")
+}
+
+fn xref_parts(xref: &str) -> Xref {
+    xref.split('・')
+        .map(|part| match part.parse::<u32>() {
+            Ok(sense) => XrefPart::Sense(sense),
+            Err(_) => XrefPart::Text(part.to_string()),
+        })
+        .collect()
+}
+
+fn common_priority(common: bool) -> Vec<String> {
+    match common {
+        true => vec![COMMON_PRIORITY.to_string()],
+        false => Vec::new(),
+    }
+}
+
+impl TryFrom<Word> for Entry {
+    type Error = anyhow::Error;
+
+    fn try_from(word: Word) -> Result<Self> {
+        let has_kanji = !word.kanji.is_empty();
+        Ok(Entry {
+            ent_seq: word
+                .id
+                .parse()
+                .map_err(|_| anyhow!("Invalid word id `{}`", word.id))?,
+            kanji: word
+                .kanji
+                .into_iter()
+                .map(|kanji| Form {
+                    text: kanji.text,
+                    info: kanji.tags,
+                    priority: common_priority(kanji.common),
+                    ..Form::default()
+                })
+                .collect(),
+            readings: word
+                .kana
+                .into_iter()
+                .map(|kana| Form {
+                    text: kana.text,
+                    info: kana.tags,
+                    priority: common_priority(kana.common),
+                    no_kanji: has_kanji && kana.applies_to_kanji.is_empty(),
+                    restrictions: restrictions(&kana.applies_to_kanji),
+                })
+                .collect(),
+            senses: word
+                .sense
+                .into_iter()
+                .map(|sense| Sense {
+                    kanji_restrictions: restrictions(&sense.applies_to_kanji),
+                    reading_restrictions: restrictions(&sense.applies_to_kana),
+                    pos: sense.part_of_speech,
+                    xrefs: sense.related.iter().map(xref_text).collect(),
+                    antonyms: sense.antonym.iter().map(xref_text).collect(),
+                    field: sense.field,
+                    misc: sense.misc,
+                    info: sense.info,
+                    dialect: sense.dialect,
+                    gloss: sense.gloss.into_iter().map(|gloss| gloss.text).collect(),
+                    ..Sense::default()
+                })
+                .collect(),
+        })
+    }
+}
+
+impl From<&Entry> for Word {
+    fn from(entry: &Entry) -> Self {
+        Word {
+            id: entry.ent_seq.to_string(),
+            kanji: entry
+                .kanji
+                .iter()
+                .map(|form| Kanji {
+                    common: form.is_common(),
+                    text: form.text.clone(),
+                    tags: form.info.clone(),
+                })
+                .collect(),
+            kana: entry
+                .readings
+                .iter()
+                .map(|form| Kana {
+                    common: form.is_common(),
+                    text: form.text.clone(),
+                    tags: form.info.clone(),
+                    applies_to_kanji: match form.no_kanji {
+                        true => Vec::new(),
+                        false => applies_to(&form.restrictions),
+                    },
+                })
+                .collect(),
+            sense: entry
+                .senses
+                .iter()
+                .map(|sense| WordSense {
+                    part_of_speech: sense.pos.clone(),
+                    applies_to_kanji: applies_to(&sense.kanji_restrictions),
+                    applies_to_kana: applies_to(&sense.reading_restrictions),
+                    related: sense.xrefs.iter().map(|xref| xref_parts(xref)).collect(),
+                    antonym: sense.antonyms.iter().map(|xref| xref_parts(xref)).collect(),
+                    field: sense.field.clone(),
+                    dialect: sense.dialect.clone(),
+                    misc: sense.misc.clone(),
+                    info: sense.info.clone(),
+                    language_source: Vec::new(),
+                    gloss: sense
+                        .gloss
+                        .iter()
+                        .map(|gloss| Gloss {
+                            lang: "eng".to_string(),
+                            text: gloss.clone(),
+                            ..Gloss::default()
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reads the words of a jmdict-simplified JSON file as JMdict entries.
+/// The document is parsed at once, the words are converted one at a time.
+///
+/// jmdict-simplified only marks forms as `common`, so their priority codes become `COMMON_PRIORITY`.
+pub struct JmdictSimplifiedReader {
+    dict_date: String,
+    tags: Vec<(String, String)>,
+    words: std::vec::IntoIter<Word>,
+}
+
+impl JmdictSimplifiedReader {
+    pub fn new<R: Read>(source: R) -> Result<Self> {
+        let document: Document = serde_json::from_reader(source)?;
+        Ok(JmdictSimplifiedReader {
+            dict_date: document.dict_date,
+            tags: document.tags.into_iter().collect(),
+            words: document.words.into_iter(),
+        })
+    }
+
+    // the date of the JMdict release the document was made from, `YYYY-MM-DD`
+    pub fn dict_date(&self) -> Option<&str> {
+        match self.dict_date.is_empty() {
+            true => None,
+            false => Some(&self.dict_date),
+        }
+    }
+}
+
+impl Iterator for JmdictSimplifiedReader {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.words.next().map(Entry::try_from)
+    }
+}
+
+impl DictionarySource for JmdictSimplifiedReader {
+    fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+}
+
+/// Writes the entries as a jmdict-simplified JSON document, one word at a time.
+/// The `tags` map holds the codes declared by the source, e.g. the DTD entities of JMdict.
+///
+/// `Entry` does not keep `<lsource>` nor the `g_type`/`g_gend` of the glosses, and JMdict has no
+/// revision list, so `languageSource` and `dictRevisions` are always empty and every gloss has
+/// a null `type` and `gender`.
+pub struct JmdictSimplifiedWriter<W: Write> {
+    output: W,
+    // `YYYY-MM-DD`
+    dict_date: String,
+    word_count: usize,
+}
+
+impl<W: Write> JmdictSimplifiedWriter<W> {
+    pub fn new(output: W, dict_date: &str) -> Self {
+        JmdictSimplifiedWriter {
+            output,
+            dict_date: dict_date.to_string(),
+            word_count: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> DictionaryWriter for JmdictSimplifiedWriter<W> {
+    fn begin(&mut self, tags: &[(String, String)]) -> Result<()> {
+        let tags = tags.iter().cloned().collect::<BTreeMap<String, String>>();
+        write!(
+            self.output,
+            r#"{{"version":{},"languages":["eng"],"commonOnly":false,"dictDate":{},"dictRevisions":[],"tags":{},"words":["#,
+            serde_json::to_string(SCHEMA_VERSION)?,
+            serde_json::to_string(&self.dict_date)?,
+            serde_json::to_string(&tags)?
+        )?;
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        if self.word_count > 0 {
+            writeln!(self.output, ",")?;
+        }
+        serde_json::to_writer(&mut self.output, &Word::from(entry))?;
+        self.word_count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        write!(self.output, "]}}")?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    use crate::jmdict_xml::JmdictReader;
+    use crate::writer::write_dictionary;
+
+    #[test]
+    fn read_jmdict_simplified_sample() {
+        let json = std::fs::read_to_string("tests/jmdict-simplified-sample.json").unwrap();
+        let reader = JmdictSimplifiedReader::new(json.as_bytes()).unwrap();
+        assert_eq!(reader.dict_date(), Some("2022-02-07"));
+        assert!(reader
+            .tags()
+            .contains(&("v5u".to_string(), "Godan verb with 'u' ending".to_string())));
+        let entries = reader.collect::<Result<Vec<Entry>>>().unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let meihaku = &entries[0];
+        assert_eq!(meihaku.ent_seq, 1000220);
+        assert_eq!(meihaku.kanji[0].priority, [COMMON_PRIORITY]);
+        assert!(meihaku.readings[0].restrictions.is_empty());
+        assert_eq!(meihaku.senses[0].xrefs, ["明らか・あきらか・1"]);
+
+        let ashirau = &entries[1];
+        assert_eq!(ashirau.senses[0].kanji_restrictions, ["遇う"]);
+        assert!(ashirau.senses[1].kanji_restrictions.is_empty());
+        assert_eq!(ashirau.senses[1].misc, ["uk"]);
+
+        let asebi = &entries[2];
+        assert!(!asebi.readings[0].no_kanji);
+        assert!(asebi.readings[4].no_kanji);
+        assert_eq!(asebi.senses[0].gloss[1], "lily-of-the-valley");
+    }
+
+    #[test]
+    fn write_jmdict_simplified_sample() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let mut writer = JmdictSimplifiedWriter::new(Vec::new(), "2022-02-07");
+        write_dictionary(
+            &mut JmdictReader::new(jmdict_xml.as_bytes()),
+            &mut [&mut writer],
+        )
+        .unwrap();
+        let json = writer.into_inner();
+
+        let document: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(document["dictDate"], "2022-02-07");
+        assert_eq!(
+            document["tags"]["uk"],
+            "word usually written using kana alone"
+        );
+        assert_eq!(
+            document["words"][0]["kanji"][0],
+            json!({"common": true, "text": "明白", "tags": []})
+        );
+        assert_eq!(
+            document["words"][1]["sense"][0]["appliesToKanji"],
+            json!(["遇う"])
+        );
+        assert_eq!(
+            document["words"][1]["sense"][1]["appliesToKanji"],
+            json!(["*"])
+        );
+        assert_eq!(document["words"][2]["kana"][4]["appliesToKanji"], json!([]));
+        assert_eq!(
+            document["words"][2]["sense"][0]["gloss"][1],
+            json!({"lang": "eng", "gender": null, "type": null, "text": "lily-of-the-valley"})
+        );
+
+        // the document reads back as the same entries, but for the priority codes
+        let entries = JmdictSimplifiedReader::new(&json[..])
+            .unwrap()
+            .collect::<Result<Vec<Entry>>>()
+            .unwrap();
+        let xml_entries = JmdictReader::new(jmdict_xml.as_bytes())
+            .collect::<Result<Vec<Entry>>>()
+            .unwrap();
+        assert_eq!(entries[1], xml_entries[1]);
+        assert_eq!(entries[2], xml_entries[2]);
+        assert_eq!(entries[0].senses, xml_entries[0].senses);
+    }
+}
@@ -1,88 +1,159 @@
-use anyhow::Result;
-use log::debug;
-use quick_xml::events::Event;
+use anyhow::{anyhow, Result};
+use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
 use regex::bytes::Regex;
 
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{BufRead, Write};
+use std::io::BufRead;
 use std::str;
 
-use crate::word_frequency::parser::WordFrequency;
-use crate::yomichan::Definition;
-use crate::yomichan::MAX_TERM_PER_BANK;
+use crate::entry::{Entry, Example, Form, Sense};
+use crate::source::DictionarySource;
 
-pub fn process_jmdict(xml: &str, vec_word_freq: &Vec<WordFrequency>) -> Result<()> {
-    let mut reader = Reader::from_str(xml);
-    reader.trim_text(true);
-
-    let mut current_term_count = 0;
-    let mut buf = Vec::new();
-    //let mut definition = String::new();
+/// JMnedict shares the entry structure of JMdict, with `<trans>` in place of `<sense>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Jmdict,
+    // also writes a tag bank of the name types declared in the DTD
+    Jmnedict,
+    // the companion dictionary listing the other forms of each headword
+    Forms,
+}
 
-    let entity_re = Regex::new(r#"<!ENTITY\s+([^ \t\r\n]+)\s+"([^"]*)"\s*>"#)?;
-    let mut custom_entities = HashMap::new();
+/// Reads the entries of a JMdict or JMnedict XML file one at a time.
+///
+/// ```no_run
+/// use jmdict_for_yomichan::JmdictReader;
+/// use std::io::BufReader;
+///
+/// let file = std::fs::File::open("JMdict_e").unwrap();
+/// for entry in JmdictReader::new(BufReader::new(file)) {
+///     println!("{}", entry.unwrap().ent_seq);
+/// }
+/// ```
+pub struct JmdictReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    custom_entities: HashMap<Vec<u8>, Vec<u8>>,
+    entity_descriptions: Vec<(String, String)>,
+    done: bool,
+}
 
-    let mut current_term_bank_count: u8 = 1;
-    let mut current_term_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(format!("term_bank_{}.json", current_term_bank_count))?;
+impl<R: BufRead> JmdictReader<R> {
+    pub fn new(source: R) -> Self {
+        let mut reader = Reader::from_reader(source);
+        reader.trim_text(true);
+        JmdictReader {
+            reader,
+            buf: Vec::new(),
+            custom_entities: HashMap::new(),
+            entity_descriptions: Vec::new(),
+            done: false,
+        }
+    }
 
-    loop {
-        match reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => match e.name() {
-                b"entry" => {
-                    current_term_count += 1;
-
-                    if current_term_count == MAX_TERM_PER_BANK {
-                        //current_term_file.flush()?;
-                        //current_term_file = OpenOptions::new()
-                        //    .create(true)
-                        //    .write(true)
-                        //    .truncate(true)
-                        //    .open(format!("term_bank_{}.json", current_term_bank_count))?;
-
-                        current_term_count = 1;
-                    }
+    /// The `<!ENTITY name "description">` declarations of the DTD read so far.
+    pub fn entity_descriptions(&self) -> &[(String, String)] {
+        &self.entity_descriptions
+    }
 
-                    let definition = parse_entry(&mut reader, &mut buf, &custom_entities)?;
-                    debug!("{:#?}", definition);
-                    write!(
-                        current_term_file,
-                        "{}",
-                        definition.serialize(current_term_count, vec_word_freq)
-                    )
-                    .unwrap();
+    fn next_entry(&mut self) -> Result<Option<Entry>> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_event(&mut self.buf) {
+                Ok(Event::Start(ref e)) if e.name() == b"entry" => {
+                    return parse_entry(&mut self.reader, &mut self.buf, &self.custom_entities)
+                        .map(Some);
                 }
-                _ => (),
-            },
-            Ok(Event::DocType(ref e)) => {
-                for cap in entity_re.captures_iter(&e) {
-                    custom_entities.insert(cap[1].to_vec(), cap[1].to_vec());
+                Ok(Event::DocType(ref e)) => {
+                    self.custom_entities = doctype_entities(e)?;
+                    self.entity_descriptions = doctype_entity_descriptions(e)?;
+                }
+                Ok(Event::Eof) => return Ok(None),
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Error at position {}: {:?}",
+                        self.reader.buffer_position(),
+                        e
+                    ))
                 }
+                _ => (),
             }
-            Ok(Event::Eof) => break,
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
-            _ => (),
         }
+    }
+}
+
+impl<R: BufRead> Iterator for JmdictReader<R> {
+    type Item = Result<Entry>;
 
-        buf.clear();
+    // reading stops at the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_entry().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
     }
-    //println!("count = {}", count);
-    //println!("txt = {:#?}", txt);
-    Ok(())
+}
+
+impl<R: BufRead> DictionarySource for JmdictReader<R> {
+    fn tags(&self) -> &[(String, String)] {
+        self.entity_descriptions()
+    }
+}
+
+// `<!ENTITY name "description">` declarations of the DTD
+fn doctype_entity_descriptions(doctype: &[u8]) -> Result<Vec<(String, String)>> {
+    let entity_re = Regex::new(r#"<!ENTITY\s+([^ \t\r\n]+)\s+"([^"]*)"\s*>"#)?;
+    Ok(entity_re
+        .captures_iter(doctype)
+        .map(|cap| {
+            (
+                String::from_utf8_lossy(&cap[1]).into_owned(),
+                String::from_utf8_lossy(&cap[2]).into_owned(),
+            )
+        })
+        .collect())
+}
+
+// every entity of the DTD expands to its own name, so `&uk;` is read as `uk`
+pub fn doctype_entities(doctype: &[u8]) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+    Ok(doctype_entity_descriptions(doctype)?
+        .into_iter()
+        .map(|(name, _)| (name.as_bytes().to_vec(), name.into_bytes()))
+        .collect())
+}
+
+// value of an attribute of an element, e.g. `r_type` of a KANJIDIC2 `<reading>`
+pub fn attribute(start: &BytesStart, key: &[u8]) -> Result<Option<Vec<u8>>> {
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        if attribute.key == key {
+            return Ok(Some(attribute.value.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+// the `<!-- JMdict created: YYYY-MM-DD -->` (or `JMnedict created`) comment written before the first entry
+pub fn jmdict_created(xml: &str) -> Option<String> {
+    let header = match xml.find("<entry>") {
+        Some(first_entry) => &xml[..first_entry],
+        None => xml,
+    };
+    let created_re =
+        regex::Regex::new(r"<!--\s*JM(?:ne)?dict created:\s*(\d{4}-\d{2}-\d{2})\s*-->")
+            .expect("Could not compile JMdict created regex");
+    created_re.captures(header).map(|cap| cap[1].to_string())
 }
 
 fn parse_entry<R: BufRead>(
     reader: &mut Reader<R>,
     buf: &mut Vec<u8>,
     custom_entities: &HashMap<Vec<u8>, Vec<u8>>,
-) -> Result<Definition> {
-    let mut definition = Definition::default();
-    let mut number_of_sense: usize = 0;
+) -> Result<Entry> {
+    let mut entry = Entry::default();
     let mut current_tag = Tag::OtherDontCareAbout;
 
     loop {
@@ -90,75 +161,105 @@ fn parse_entry<R: BufRead>(
             // a tag was opened
             Event::Start(start) => {
                 current_tag = Tag::from_str(str::from_utf8(start.name())?);
-                if current_tag == Tag::Sense {
-                    number_of_sense += 1;
+                match current_tag {
+                    Tag::Keb => entry.kanji.push(Form::default()),
+                    Tag::Reb => entry.readings.push(Form::default()),
+                    Tag::Sense => entry.senses.push(Sense::default()),
+                    Tag::Example => last(&mut entry.senses)?.examples.push(Example::default()),
+                    // the Japanese sentence is `xml:lang="jpn"`, its translation `xml:lang="eng"`
+                    Tag::ExSent if attribute(&start, b"xml:lang")?.as_deref() != Some(b"jpn") => {
+                        current_tag = Tag::ExTranslation;
+                    }
+                    _ => (),
                 }
             }
             Event::Text(text) => {
                 let value =
-                    text.unescape_and_decode_with_custom_entities(&reader, &custom_entities)?;
+                    text.unescape_and_decode_with_custom_entities(reader, custom_entities)?;
                 match current_tag {
                     Tag::EntSeq => {
-                        definition.sequence_number(
-                            value
-                                .parse::<u32>()
-                                .expect("could not parse sequence_number to u32"),
-                        );
-                    }
-                    Tag::Keb => {
-                        definition.add_term(value);
+                        entry.ent_seq = value
+                            .parse::<u32>()
+                            .map_err(|_| anyhow!("Invalid ent_seq `{}`", value))?;
                     }
-                    Tag::Reb => {
-                        definition.add_reading(value);
+                    Tag::Keb => last(&mut entry.kanji)?.text = value,
+                    Tag::KeInf => last(&mut entry.kanji)?.info.push(value),
+                    Tag::KePri => last(&mut entry.kanji)?.priority.push(value),
+                    Tag::Reb => last(&mut entry.readings)?.text = value,
+                    Tag::ReInf => last(&mut entry.readings)?.info.push(value),
+                    Tag::RePri => last(&mut entry.readings)?.priority.push(value),
+                    Tag::ReRestr => last(&mut entry.readings)?.restrictions.push(value),
+                    Tag::Stagk => last(&mut entry.senses)?.kanji_restrictions.push(value),
+                    Tag::Stagr => last(&mut entry.senses)?.reading_restrictions.push(value),
+                    Tag::Pos => last(&mut entry.senses)?.pos.push(value),
+                    Tag::Xref => last(&mut entry.senses)?.xrefs.push(value),
+                    Tag::Ant => last(&mut entry.senses)?.antonyms.push(value),
+                    Tag::Field => last(&mut entry.senses)?.field.push(value),
+                    Tag::Misc => last(&mut entry.senses)?.misc.push(value),
+                    Tag::SInf => last(&mut entry.senses)?.info.push(value),
+                    Tag::Dial => last(&mut entry.senses)?.dialect.push(value),
+                    Tag::NameType => last(&mut entry.senses)?.name_type.push(value),
+                    Tag::Gloss => last(&mut entry.senses)?.gloss.push(value),
+                    Tag::ExText => last(&mut last(&mut entry.senses)?.examples)?.text = value,
+                    Tag::ExSent => last(&mut last(&mut entry.senses)?.examples)?.sentence = value,
+                    Tag::ExTranslation => {
+                        last(&mut last(&mut entry.senses)?.examples)?.translation = value
                     }
-                    Tag::Pos => {
-                        definition.add_pos(value, number_of_sense);
-                    }
-                    Tag::Misc => {
-                        match value.as_str() {
-                            "uk" => {
-                                definition.set_uk();
-                            }
-                            _ => (),
-                        };
-
-                        definition.add_misc(value, number_of_sense);
-                    }
-                    Tag::Gloss => {
-                        definition.add_gloss(value, number_of_sense);
-                    }
-                    Tag::Sense => {
-                        number_of_sense += 1;
-                    }
-                    Tag::OtherDontCareAbout => (),
+                    Tag::Sense | Tag::Example | Tag::OtherDontCareAbout => (),
                 }
             }
-            Event::Empty(_val) => {
-                definition.set_uk();
+            Event::Empty(empty) if empty.name() == b"re_nokanji" => {
+                if let Some(reading) = entry.readings.last_mut() {
+                    reading.no_kanji = true;
+                }
             }
             Event::End(end) => {
+                current_tag = Tag::OtherDontCareAbout;
                 if end.name() == b"entry" {
                     break;
-                } else if end.name() == b"sense" {
-                    definition.increase_sense();
                 }
             }
+            Event::Eof => return Err(anyhow!("Unexpected end of file in entry {}", entry.ent_seq)),
             _ => (),
         }
     }
-    Ok(definition)
+    Ok(entry)
+}
+
+// the element being filled, a child is always read after its parent was opened
+fn last<T>(elements: &mut [T]) -> Result<&mut T> {
+    elements
+        .last_mut()
+        .ok_or_else(|| anyhow!("Element outside of its parent element"))
 }
 
 #[derive(PartialEq)]
 enum Tag {
-    // TODO: Add x_inf (see あそこ)
     EntSeq,
     Keb,
+    KeInf,
+    KePri,
     Reb,
+    ReInf,
+    RePri,
+    ReRestr,
+    Stagk,
+    Stagr,
     Pos,
-    Gloss,
+    Xref,
+    Ant,
+    Field,
     Misc,
+    SInf,
+    Dial,
+    NameType,
+    Gloss,
     Sense,
+    Example,
+    ExText,
+    ExSent,
+    // an <ex_sent> that is not the Japanese sentence
+    ExTranslation,
     OtherDontCareAbout,
 }
 
@@ -167,12 +268,79 @@ impl Tag {
         match s {
             "ent_seq" => Tag::EntSeq,
             "keb" => Tag::Keb,
+            "ke_inf" => Tag::KeInf,
+            "ke_pri" => Tag::KePri,
             "reb" => Tag::Reb,
+            "re_inf" => Tag::ReInf,
+            "re_pri" => Tag::RePri,
+            "re_restr" => Tag::ReRestr,
+            "stagk" => Tag::Stagk,
+            "stagr" => Tag::Stagr,
             "pos" => Tag::Pos,
-            "gloss" => Tag::Gloss,
+            "xref" => Tag::Xref,
+            "ant" => Tag::Ant,
+            "field" => Tag::Field,
             "misc" => Tag::Misc,
-            "sense" => Tag::Sense,
+            "s_inf" => Tag::SInf,
+            "dial" => Tag::Dial,
+            "name_type" => Tag::NameType,
+            "gloss" | "trans_det" => Tag::Gloss,
+            "sense" | "trans" => Tag::Sense,
+            "example" => Tag::Example,
+            "ex_text" => Tag::ExText,
+            "ex_sent" => Tag::ExSent,
             _ => Tag::OtherDontCareAbout,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn read_sample_entries() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let entries = JmdictReader::new(jmdict_xml.as_bytes())
+            .collect::<Result<Vec<Entry>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let ashirau = &entries[1];
+        assert_eq!(ashirau.ent_seq, 1000300);
+        assert_eq!(
+            ashirau.kanji,
+            [Form::new("遇う".to_string()), Form::new("配う".to_string())]
+        );
+        assert_eq!(ashirau.senses[0].kanji_restrictions, ["遇う"]);
+        assert_eq!(ashirau.senses[1].pos, ["v5u", "vt"]);
+        assert_eq!(ashirau.senses[1].misc, ["uk"]);
+        assert!(entries[2].readings[4].no_kanji);
+
+        // the entries go through serde unchanged
+        let json = serde_json::to_value(&entries[2]).unwrap();
+        assert_eq!(
+            json["readings"][4],
+            json!({"text": "アセビ", "no_kanji": true})
+        );
+        assert_eq!(serde_json::from_value::<Entry>(json).unwrap(), entries[2]);
+    }
+
+    #[test]
+    fn read_broken_entries() {
+        let mut reader =
+            JmdictReader::new(&b"<JMdict><entry><ent_seq>1x</ent_seq></entry></JMdict>"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+        let mut reader = JmdictReader::new(&b"<JMdict><entry><ent_seq>1</ent_seq>"[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn jmdict_created_sample() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        assert_eq!(jmdict_created(&jmdict_xml), Some("2022-02-07".to_string()));
+        assert_eq!(jmdict_created("<JMdict><entry></entry></JMdict>"), None);
+    }
+}
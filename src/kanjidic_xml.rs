@@ -0,0 +1,217 @@
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use std::collections::HashMap;
+use std::io::{BufRead, Seek, Write};
+
+use crate::archive::DictArchive;
+use crate::jmdict_xml::{attribute, doctype_entities};
+use crate::yomichan::{Kanji, KANJI_TAGS};
+
+/// Writes the kanji_bank, kanji_meta_bank (newspaper frequency rank) and tag_bank of KANJIDIC2.
+pub fn process_kanjidic<W: Write + Seek>(xml: &str, archive: &mut DictArchive<W>) -> Result<()> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut custom_entities = HashMap::new();
+    // written after the kanji bank, the archive writes one kind of bank at a time
+    let mut meta_rows = Vec::new();
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) if e.name() == b"character" => {
+                let kanji = parse_character(&mut reader, &mut buf, &custom_entities)?;
+                debug!("{:#?}", kanji);
+                archive.write_row("kanji", &kanji.serialize())?;
+                meta_rows.extend(kanji.serialize_meta());
+            }
+            Ok(Event::DocType(ref e)) => {
+                custom_entities = doctype_entities(e)?;
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(anyhow!(
+                    "Error at position {}: {:?}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => (),
+        }
+
+        buf.clear();
+    }
+
+    for row in meta_rows {
+        archive.write_row("kanji_meta", &row)?;
+    }
+    for row in KANJI_TAGS {
+        archive.write_row("tag", row)?;
+    }
+    Ok(())
+}
+
+// the `<date_of_creation>` of the KANJIDIC2 header
+pub fn kanjidic_created(xml: &str) -> Option<String> {
+    let header = match xml.find("<character>") {
+        Some(first_character) => &xml[..first_character],
+        None => xml,
+    };
+    let created_re =
+        regex::Regex::new(r"<date_of_creation>\s*(\d{4}-\d{2}-\d{2})\s*</date_of_creation>")
+            .expect("Could not compile KANJIDIC2 date_of_creation regex");
+    created_re.captures(header).map(|cap| cap[1].to_string())
+}
+
+fn parse_character<R: BufRead>(
+    reader: &mut Reader<R>,
+    buf: &mut Vec<u8>,
+    custom_entities: &HashMap<Vec<u8>, Vec<u8>>,
+) -> Result<Kanji> {
+    let mut kanji = Kanji::default();
+    let mut current_tag = Tag::OtherDontCareAbout;
+
+    loop {
+        match reader.read_event(buf)? {
+            Event::Start(start) => {
+                current_tag = Tag::from_start(&start)?;
+            }
+            Event::Text(text) => {
+                let value =
+                    text.unescape_and_decode_with_custom_entities(reader, custom_entities)?;
+                match current_tag {
+                    Tag::Literal => {
+                        kanji.literal(value);
+                    }
+                    Tag::Onyomi => {
+                        kanji.add_onyomi(value);
+                    }
+                    Tag::Kunyomi => {
+                        kanji.add_kunyomi(value);
+                    }
+                    Tag::Meaning => {
+                        kanji.add_meaning(value);
+                    }
+                    Tag::StrokeCount => {
+                        kanji.stroke_count(value.parse().context("Invalid stroke_count")?);
+                    }
+                    Tag::Grade => {
+                        kanji.grade(value.parse().context("Invalid grade")?);
+                    }
+                    Tag::Jlpt => {
+                        kanji.jlpt(value.parse().context("Invalid jlpt")?);
+                    }
+                    Tag::Freq => {
+                        kanji.frequency(value.parse().context("Invalid freq")?);
+                    }
+                    Tag::OtherDontCareAbout => (),
+                }
+            }
+            Event::End(end) => {
+                current_tag = Tag::OtherDontCareAbout;
+                if end.name() == b"character" {
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(kanji)
+}
+
+#[derive(PartialEq)]
+enum Tag {
+    Literal,
+    Onyomi,
+    Kunyomi,
+    Meaning,
+    StrokeCount,
+    Grade,
+    Jlpt,
+    Freq,
+    OtherDontCareAbout,
+}
+
+impl Tag {
+    // readings and meanings also depend on their `r_type` and `m_lang` attributes
+    fn from_start(start: &BytesStart) -> Result<Self> {
+        Ok(match start.name() {
+            b"literal" => Tag::Literal,
+            b"reading" => match attribute(start, b"r_type")?.as_deref() {
+                Some(b"ja_on") => Tag::Onyomi,
+                Some(b"ja_kun") => Tag::Kunyomi,
+                _ => Tag::OtherDontCareAbout,
+            },
+            // meanings without `m_lang` are in English
+            b"meaning" => match attribute(start, b"m_lang")?.as_deref() {
+                None | Some(b"en") => Tag::Meaning,
+                _ => Tag::OtherDontCareAbout,
+            },
+            b"stroke_count" => Tag::StrokeCount,
+            b"grade" => Tag::Grade,
+            b"jlpt" => Tag::Jlpt,
+            b"freq" => Tag::Freq,
+            _ => Tag::OtherDontCareAbout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    use crate::yomichan::DictIndex;
+
+    #[test]
+    fn process_kanjidic_sample() {
+        let kanjidic_xml = std::fs::read_to_string("tests/kanjidic-sample.xml").unwrap();
+        assert_eq!(
+            kanjidic_created(&kanjidic_xml),
+            Some("2022-02-04".to_string())
+        );
+
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        process_kanjidic(&kanjidic_xml, &mut archive).unwrap();
+        let writer = archive
+            .finish(&DictIndex::new("KANJIDIC", "20220204"))
+            .unwrap();
+        let mut zip = ZipArchive::new(writer).unwrap();
+
+        let mut kanji_bank = String::new();
+        zip.by_name("kanji_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut kanji_bank)
+            .unwrap();
+        let rows = kanji_bank.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            rows,
+            [
+                r#"[["亜","ア","つ.ぐ","jouyou",["Asia","rank next","come after","-ous"],{"freq":"1509","grade":"8","jlpt":"1","strokes":"7"}],"#,
+                r#"["唖","ア アク","おし","",["mute","dumb"],{"strokes":"10"}],"#,
+                r#"["日","ニチ ジツ","ひ -び -か","kyouiku",["day","sun","Japan","counter for days"],{"freq":"1","grade":"1","jlpt":"4","strokes":"4"}]]"#,
+            ]
+        );
+
+        let mut kanji_meta_bank = String::new();
+        zip.by_name("kanji_meta_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut kanji_meta_bank)
+            .unwrap();
+        assert_eq!(
+            kanji_meta_bank,
+            "[[\"亜\",\"freq\",1509],\n[\"日\",\"freq\",1]]"
+        );
+        assert!(zip.by_name("tag_bank_1.json").is_ok());
+    }
+
+    #[test]
+    fn malformed_kanjidic() {
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        assert!(process_kanjidic("<kanjidic2><header></kanjidic2>", &mut archive).is_err());
+    }
+}
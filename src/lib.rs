@@ -0,0 +1,29 @@
+//! Reads JMdict, JMnedict and KANJIDIC2 and writes them as Yomichan dictionaries.
+//! `JmdictReader` gives the entries of JMdict to other tools as `Entry` values,
+//! and a `DictionaryWriter` turns them into one of the output formats.
+//! EDICT2 and jmdict-simplified files are read into the same entries by their `DictionarySource`.
+
+pub mod archive;
+pub mod edict;
+pub mod entry;
+pub mod filter;
+pub mod form_policy;
+pub mod furigana;
+pub mod jmdict_simplified;
+pub mod jmdict_xml;
+pub mod kanjidic_xml;
+pub mod pitch_accent;
+pub mod score;
+pub mod source;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stardict;
+pub mod validate;
+pub mod word_frequency;
+pub mod writer;
+pub mod yomichan;
+
+pub use entry::{Entry, Example, Form, Sense};
+pub use jmdict_xml::JmdictReader;
+pub use source::DictionarySource;
+pub use writer::DictionaryWriter;
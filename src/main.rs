@@ -1,20 +1,40 @@
-use anyhow::Result;
-use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg};
+use anyhow::{bail, Context, Result};
+use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg, ArgMatches};
 use fern::colors::{Color, ColoredLevelConfig};
 use fs2::FileExt;
-use log::{debug, info, LevelFilter};
+use log::{debug, info, warn, LevelFilter};
 
-use std::fs::OpenOptions;
-use std::io::{stdout, Write};
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{stdout, BufWriter};
+use std::path::{Path, PathBuf};
 
 use time::format_description::well_known::Rfc3339;
 
-mod jmdict_xml;
-mod word_frequency;
-mod yomichan;
+use jmdict_for_yomichan::archive::{archive_path, write_published_index, DictArchive};
+use jmdict_for_yomichan::filter::Filter;
+use jmdict_for_yomichan::form_policy::FormPolicies;
+use jmdict_for_yomichan::furigana::Furigana;
+use jmdict_for_yomichan::jmdict_simplified::JmdictSimplifiedWriter;
+use jmdict_for_yomichan::jmdict_xml::{self, JmdictReader, Mode};
+use jmdict_for_yomichan::kanjidic_xml;
+use jmdict_for_yomichan::pitch_accent::{read_pitch_file, PitchAccents};
+use jmdict_for_yomichan::score::ScoreWeights;
+use jmdict_for_yomichan::source::{open_source, FilteredSource, InputFormat};
+#[cfg(feature = "sqlite")]
+use jmdict_for_yomichan::sqlite::SqliteWriter;
+use jmdict_for_yomichan::stardict::StardictWriter;
+use jmdict_for_yomichan::validate::{check_dictionary, Validator};
+use jmdict_for_yomichan::yomichan::{
+    self, revision_date, separated_revision_date, DictIndex, FrequencyMeta, GlossaryOptions,
+    IndexMetadata, KANJIDIC_ATTRIBUTION,
+};
 
-use yomichan::DictIndex;
+use jmdict_for_yomichan::word_frequency::merge::{merge_sources, FrequencySource, MergeStrategy};
+use jmdict_for_yomichan::word_frequency::parser::{read_frequency_file, WordFrequency};
+use jmdict_for_yomichan::word_frequency::stats::{
+    MissingPopularity, Normalization, Popularity, NEUTRAL_POPULARITY,
+};
+use jmdict_for_yomichan::writer::{write_dictionary, DictionaryWriter, YomichanWriter};
 
 const PROGRAM_NAME: &str = "jmdict_for_yomichan";
 
@@ -41,8 +61,9 @@ fn setup_logging(verbosity: u64, chain: bool, log_path: Option<&str>) -> Result<
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "{date} {colored_level} > {colored_message}",
+                // the local offset is not available in every container, fall back to UTC there
                 date = time::OffsetDateTime::now_local()
-                    .expect("Could not get local time")
+                    .unwrap_or_else(|_| time::OffsetDateTime::now_utc())
                     .format(&time_cli_format)
                     .expect("Could not parse to %H:%M:%S"),
                 colored_level = format_args!(
@@ -99,9 +120,434 @@ fn setup_logging(verbosity: u64, chain: bool, log_path: Option<&str>) -> Result<
     Ok(log_path)
 }
 
+// fields of the config file are overridden by the ones given on the command line
+fn index_metadata(matches: &ArgMatches) -> Result<IndexMetadata> {
+    let mut metadata = match matches.value_of("index-config") {
+        Some(path) => IndexMetadata::from_file(path)?,
+        None => IndexMetadata::default(),
+    };
+    let fields = [
+        ("title", &mut metadata.title),
+        ("author", &mut metadata.author),
+        ("url", &mut metadata.url),
+        ("description", &mut metadata.description),
+        ("attribution", &mut metadata.attribution),
+        ("source-language", &mut metadata.source_language),
+        ("target-language", &mut metadata.target_language),
+        ("frequency-mode", &mut metadata.frequency_mode),
+    ];
+    for (arg, field) in fields {
+        if let Some(value) = matches.value_of(arg) {
+            *field = Some(value.to_string());
+        }
+    }
+    Ok(metadata)
+}
+
+// a list weight divides the weighted mean, so it has to be a positive number
+fn parse_weight(weight: &str) -> Result<f32> {
+    match weight.parse::<f32>() {
+        Ok(weight) if weight.is_finite() && weight > 0f32 => Ok(weight),
+        _ => bail!("expected a positive number, got `{}`", weight),
+    }
+}
+
+// `--sqlite` only exists in builds with the `sqlite` feature
+#[cfg(feature = "sqlite")]
+fn sqlite_args<'help>() -> Vec<Arg<'help>> {
+    vec![Arg::new("sqlite")
+        .long("sqlite")
+        .help("Also write every entry with its forms, senses, tags, cross-references and popularity to an SQLite database (`JMdict.sqlite`)")]
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_args<'help>() -> Vec<Arg<'help>> {
+    Vec::new()
+}
+
+fn build_dictionaries(matches: &ArgMatches) -> Result<()> {
+    let vec_word_freq: Vec<WordFrequency> = match matches.values_of("frequency") {
+        Some(frequency_paths) => {
+            let frequency_paths = frequency_paths.collect::<Vec<&str>>();
+            let weights = matches
+                .values_of("weight")
+                .into_iter()
+                .flatten()
+                .collect::<Vec<&str>>();
+            if weights.len() > frequency_paths.len() {
+                bail!("Got more weights than frequency lists");
+            }
+
+            let mut sources = Vec::new();
+            for (i, frequency_path) in frequency_paths.into_iter().enumerate() {
+                let weight = match weights.get(i) {
+                    Some(weight) => parse_weight(weight).with_context(|| {
+                        format!("Invalid weight of frequency list `{}`", frequency_path)
+                    })?,
+                    None => 1f32,
+                };
+                sources.push(FrequencySource {
+                    weight,
+                    words: read_frequency_file(frequency_path)?,
+                });
+                info!(
+                    "Loaded frequency list `{}` with weight {}",
+                    frequency_path, weight
+                );
+            }
+            let strategy = matches
+                .value_of("merge-strategy")
+                .unwrap()
+                .parse::<MergeStrategy>()?;
+            merge_sources(sources, strategy)
+        }
+        None => {
+            info!(
+                "No frequency list given, every term gets the neutral popularity {}",
+                NEUTRAL_POPULARITY
+            );
+            Vec::new()
+        }
+    };
+
+    let output_dir = Path::new(matches.value_of("output").unwrap());
+    std::fs::create_dir_all(output_dir)?;
+
+    let input_path = matches.value_of("input").unwrap();
+    let jmdict_text = std::fs::read_to_string(input_path)?;
+    let input_format = matches
+        .value_of("input-format")
+        .unwrap()
+        .parse::<InputFormat>()?;
+    let (mut source, created) = open_source(input_format, &jmdict_text)?;
+    let revision_date = revision_date(created, std::env::var("SOURCE_DATE_EPOCH").ok())?;
+    info!("Dictionary revision date is {}", revision_date);
+
+    let publish_url = matches.value_of("publish-url");
+    let metadata = index_metadata(matches)?;
+    let mut dictionary_index = DictIndex::new("JMdict", &revision_date);
+    dictionary_index.metadata(&IndexMetadata {
+        frequency_mode: None,
+        ..metadata.clone()
+    });
+    if let Some(base_url) = publish_url {
+        dictionary_index.updatable(base_url, "JMdict");
+    }
+
+    let mut written_paths = Vec::new();
+    if matches.is_present("frequency-dictionary") {
+        let frequency_mode = metadata.frequency_mode.as_deref().unwrap_or("rank-based");
+        let frequency_path = archive_path(output_dir, "JMdict_freq", publish_url.is_some())?;
+        let mut archive = DictArchive::create(&frequency_path)?;
+        for frequency_meta in FrequencyMeta::from_word_frequency(&vec_word_freq) {
+            archive.write_row("term_meta", &frequency_meta.serialize(frequency_mode))?;
+        }
+        let mut index = DictIndex::new("JMdict Frequency", &revision_date);
+        index.metadata(&IndexMetadata {
+            title: Some(format!("{} Frequency", dictionary_index.title())),
+            frequency_mode: Some(frequency_mode.to_string()),
+            ..metadata.clone()
+        });
+        if let Some(base_url) = publish_url {
+            index.updatable(base_url, "JMdict_freq");
+        }
+        archive.finish(&index)?;
+        if publish_url.is_some() {
+            write_published_index(output_dir, "JMdict_freq", &index)?;
+        }
+        info!("Successfully wrote `{}`", frequency_path.display());
+        written_paths.push(frequency_path);
+    }
+
+    let popularity = Popularity::new(
+        &vec_word_freq,
+        matches
+            .value_of("normalization")
+            .unwrap()
+            .parse::<Normalization>()?,
+        matches
+            .value_of("missing-popularity")
+            .unwrap()
+            .parse::<MissingPopularity>()?,
+    );
+
+    let mut weights = ScoreWeights::default();
+    if let Some(score_weights) = matches.values_of("score-weight") {
+        for score_weight in score_weights {
+            weights
+                .set(score_weight)
+                .expect("score weights are checked by clap");
+        }
+    }
+
+    let mut form_policies = FormPolicies::default();
+    if let Some(code_policies) = matches.values_of("form-policy") {
+        for code_policy in code_policies {
+            form_policies
+                .set(code_policy)
+                .expect("form policies are checked by clap");
+        }
+    }
+
+    let filter = match matches.value_of("filter") {
+        Some(expression) => Some(Filter::parse(expression)?),
+        None => None,
+    };
+
+    let glossary_options = GlossaryOptions {
+        structured_content: matches.is_present("structured-content"),
+        max_examples: matches
+            .value_of("max-examples")
+            .unwrap()
+            .parse()
+            .expect("max examples are checked by clap"),
+        furigana: match matches.value_of("furigana") {
+            Some(furigana_path) => Furigana::from_file(furigana_path)?,
+            None => Furigana::default(),
+        },
+        forms_table: matches.is_present("forms-table"),
+    };
+
+    let mut pitch_accents = match matches.value_of("pitch-accent") {
+        Some(pitch_path) => {
+            let vec_pitch = read_pitch_file(pitch_path)?;
+            info!(
+                "Loaded {} pitch accent(s) from `{}`",
+                vec_pitch.len(),
+                pitch_path
+            );
+            PitchAccents::new(vec_pitch)
+        }
+        None => PitchAccents::default(),
+    };
+
+    let dictionary_path = archive_path(output_dir, "JMdict", publish_url.is_some())?;
+    let mut archive = DictArchive::create(&dictionary_path)?;
+    let mut forms_archive = None;
+    if matches.is_present("forms-dictionary") {
+        let forms_path = archive_path(output_dir, "JMdict_forms", publish_url.is_some())?;
+        forms_archive = Some((DictArchive::create(&forms_path)?, forms_path));
+    }
+    let simplified_path = matches
+        .is_present("jmdict-simplified")
+        .then(|| output_dir.join("jmdict-eng.json"));
+    #[cfg(feature = "sqlite")]
+    let sqlite_path = matches
+        .is_present("sqlite")
+        .then(|| output_dir.join("JMdict.sqlite"));
+    #[cfg(not(feature = "sqlite"))]
+    let sqlite_path: Option<PathBuf> = None;
+    let mut stardict_dir = None;
+    if matches.is_present("stardict") {
+        let dir = output_dir.join("JMdict_stardict");
+        std::fs::create_dir_all(&dir)?;
+        stardict_dir = Some(dir);
+    }
+    {
+        let mut writer = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmdict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            Some(&mut pitch_accents),
+        );
+        let mut forms_writer = forms_archive.as_mut().map(|(forms_archive, _)| {
+            YomichanWriter::new(
+                forms_archive,
+                Mode::Forms,
+                &popularity,
+                &weights,
+                &form_policies,
+                &glossary_options,
+                None,
+            )
+        });
+        let mut simplified_writer = match &simplified_path {
+            Some(simplified_path) => Some(JmdictSimplifiedWriter::new(
+                BufWriter::new(File::create(simplified_path)?),
+                &separated_revision_date(&revision_date, '-')?,
+            )),
+            None => None,
+        };
+        let mut stardict_writer = match &stardict_dir {
+            Some(stardict_dir) => Some(StardictWriter::new(
+                &stardict_dir.join("JMdict"),
+                dictionary_index.title(),
+                &revision_date,
+                &metadata,
+                !matches.is_present("stardict-uncompressed"),
+            )?),
+            None => None,
+        };
+        #[cfg(feature = "sqlite")]
+        let mut sqlite_writer = match &sqlite_path {
+            Some(sqlite_path) => Some(SqliteWriter::create(sqlite_path, &popularity)?),
+            None => None,
+        };
+        // the forms dictionary and the exports are written from the same parse of JMdict
+        let mut writers: Vec<&mut dyn DictionaryWriter> = vec![&mut writer];
+        if let Some(forms_writer) = forms_writer.as_mut() {
+            writers.push(forms_writer);
+        }
+        if let Some(simplified_writer) = simplified_writer.as_mut() {
+            writers.push(simplified_writer);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlite_writer) = sqlite_writer.as_mut() {
+            writers.push(sqlite_writer);
+        }
+        if let Some(stardict_writer) = stardict_writer.as_mut() {
+            writers.push(stardict_writer);
+        }
+        // every writer gets the same entries, filtered once
+        let mut filtered_source =
+            FilteredSource::new(source.as_mut(), filter.as_ref(), &form_policies);
+        write_dictionary(&mut filtered_source, &mut writers)?;
+        if filter.is_some() {
+            info!(
+                "Left out {} entries that do not match the filter",
+                filtered_source.filtered_out()
+            );
+        }
+    }
+    info!("Wrote {} term bank(s)", archive.bank_count("term"));
+    let unmatched_pitch = pitch_accents.unmatched();
+    if !unmatched_pitch.is_empty() {
+        warn!(
+            "{} pitch accent(s) match no JMdict form, run with -v to list them",
+            unmatched_pitch.len()
+        );
+        for unmatched in unmatched_pitch {
+            info!("No JMdict form matches the pitch accent of {}", unmatched);
+        }
+    }
+    archive.finish(&dictionary_index)?;
+    if publish_url.is_some() {
+        write_published_index(output_dir, "JMdict", &dictionary_index)?;
+    }
+    info!("Successfully wrote `{}`", dictionary_path.display());
+    written_paths.push(dictionary_path);
+
+    for export_path in [simplified_path, sqlite_path, stardict_dir]
+        .into_iter()
+        .flatten()
+    {
+        info!("Successfully wrote `{}`", export_path.display());
+    }
+
+    if let Some((archive, forms_path)) = forms_archive {
+        info!("Wrote {} forms bank(s)", archive.bank_count("term"));
+        let mut index = DictIndex::new("JMdict Forms", &revision_date);
+        index.metadata(&IndexMetadata {
+            title: Some(format!("{} Forms", dictionary_index.title())),
+            frequency_mode: None,
+            ..metadata.clone()
+        });
+        if let Some(base_url) = publish_url {
+            index.updatable(base_url, "JMdict_forms");
+        }
+        archive.finish(&index)?;
+        if publish_url.is_some() {
+            write_published_index(output_dir, "JMdict_forms", &index)?;
+        }
+        info!("Successfully wrote `{}`", forms_path.display());
+        written_paths.push(forms_path);
+    }
+
+    if let Some(jmnedict_path) = matches.value_of("jmnedict") {
+        let jmnedict_xml = std::fs::read_to_string(jmnedict_path)?;
+        let jmnedict_revision_date = yomichan::revision_date(
+            jmdict_xml::jmdict_created(&jmnedict_xml),
+            std::env::var("SOURCE_DATE_EPOCH").ok(),
+        )?;
+        let names_path = archive_path(output_dir, "JMnedict", publish_url.is_some())?;
+        let mut archive = DictArchive::create(&names_path)?;
+        let mut writer = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmnedict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            None,
+        );
+        // the filter keys are the ones of JMdict, only the form policies apply to names
+        let mut reader = JmdictReader::new(jmnedict_xml.as_bytes());
+        write_dictionary(
+            &mut FilteredSource::new(&mut reader, None, &form_policies),
+            &mut [&mut writer],
+        )?;
+        info!("Wrote {} name bank(s)", archive.bank_count("term"));
+        let mut index = DictIndex::new("JMnedict", &jmnedict_revision_date);
+        index.source("JMnedict", &jmnedict_revision_date, true);
+        // the title and description given on the command line are the ones of JMdict
+        index.metadata(&IndexMetadata {
+            title: None,
+            description: None,
+            frequency_mode: None,
+            ..metadata.clone()
+        });
+        if let Some(base_url) = publish_url {
+            index.updatable(base_url, "JMnedict");
+        }
+        archive.finish(&index)?;
+        if publish_url.is_some() {
+            write_published_index(output_dir, "JMnedict", &index)?;
+        }
+        info!("Successfully wrote `{}`", names_path.display());
+        written_paths.push(names_path);
+    }
+
+    if let Some(kanjidic_path) = matches.value_of("kanjidic") {
+        let kanjidic_xml = std::fs::read_to_string(kanjidic_path)?;
+        let kanjidic_revision_date = yomichan::revision_date(
+            kanjidic_xml::kanjidic_created(&kanjidic_xml),
+            std::env::var("SOURCE_DATE_EPOCH").ok(),
+        )?;
+        let kanji_path = archive_path(output_dir, "KANJIDIC", publish_url.is_some())?;
+        let mut archive = DictArchive::create(&kanji_path)?;
+        kanjidic_xml::process_kanjidic(&kanjidic_xml, &mut archive)?;
+        info!("Wrote {} kanji bank(s)", archive.bank_count("kanji"));
+        let mut index = DictIndex::new("KANJIDIC", &kanjidic_revision_date);
+        index.source("KANJIDIC2", &kanjidic_revision_date, false);
+        // the title and description given on the command line are the ones of JMdict
+        index.metadata(&IndexMetadata {
+            title: None,
+            description: None,
+            attribution: Some(
+                metadata
+                    .attribution
+                    .clone()
+                    .unwrap_or_else(|| KANJIDIC_ATTRIBUTION.to_string()),
+            ),
+            frequency_mode: Some("rank-based".to_string()),
+            ..metadata.clone()
+        });
+        if let Some(base_url) = publish_url {
+            index.updatable(base_url, "KANJIDIC");
+        }
+        archive.finish(&index)?;
+        if publish_url.is_some() {
+            write_published_index(output_dir, "KANJIDIC", &index)?;
+        }
+        info!("Successfully wrote `{}`", kanji_path.display());
+        written_paths.push(kanji_path);
+    }
+
+    // catch a malformed row here rather than when Yomichan refuses to import the archive
+    let validator = Validator::new()?;
+    for path in written_paths {
+        check_dictionary(&validator, &path)?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let matches = App::new(PROGRAM_NAME)
         .setting(AppSettings::DisableHelpSubcommand)
+        .setting(AppSettings::SubcommandsNegateReqs)
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
@@ -112,10 +558,204 @@ fn main() -> Result<()> {
                 .takes_value(true)
                 .required(true),
         )
+        .subcommand(
+            App::new("validate")
+                .about("Check a dictionary (zip archive or unpacked directory) against the Yomichan schemas")
+                .arg(
+                    Arg::new("path")
+                        .help("Dictionary archive or directory")
+                        .index(1)
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .arg(
+            Arg::new("input-format")
+                .long("input-format")
+                .takes_value(true)
+                .possible_values(["jmdict", "edict2", "jmdict-simplified"])
+                .default_value("jmdict")
+                .help("Format of the input file: JMdict XML, EDICT2 text (UTF-8) or jmdict-simplified JSON. The last two only mark forms as common, which scores like the `common` weight of --score-weight"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .takes_value(true)
+                .default_value(".")
+                .help("Directory the dictionary archives are written to"),
+        )
+        .arg(
+            Arg::new("jmnedict")
+                .long("jmnedict")
+                .takes_value(true)
+                .help("JMnedict XML file, also builds a names dictionary from it"),
+        )
+        .arg(
+            Arg::new("kanjidic")
+                .long("kanjidic")
+                .takes_value(true)
+                .help("KANJIDIC2 XML file, also builds a kanji dictionary from it"),
+        )
+        .arg(
+            Arg::new("publish-url")
+                .long("publish-url")
+                .takes_value(true)
+                .help("Base URL the output directory is served from, makes the dictionaries auto-updatable in Yomichan"),
+        )
+        .arg(
+            Arg::new("frequency")
+                .short('f')
+                .long("frequency")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Frequency list used to score terms, can be given multiple times (terms get a neutral score if omitted)"),
+        )
+        .arg(
+            Arg::new("weight")
+                .short('w')
+                .long("weight")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(parse_weight)
+                .help("Weight of the frequency list given at the same position, a positive number (default: 1)"),
+        )
+        .arg(
+            Arg::new("merge-strategy")
+                .long("merge-strategy")
+                .takes_value(true)
+                .possible_values(["weighted-mean", "max", "rank-average"])
+                .default_value("weighted-mean")
+                .help("How popularity from several frequency lists is combined"),
+        )
+        .arg(
+            Arg::new("normalization")
+                .long("normalization")
+                .takes_value(true)
+                .possible_values(["raw", "z-score", "percentile", "log", "rank"])
+                .default_value("raw")
+                .help("How popularity is scaled before it is used as the term score"),
+        )
+        .arg(
+            Arg::new("missing-popularity")
+                .long("missing-popularity")
+                .takes_value(true)
+                .default_value("mean-minus-std-dev")
+                .validator(|s| s.parse::<MissingPopularity>().map(|_| ()))
+                .help("Popularity of entries missing from every frequency list (mean-minus-std-dev, mean, min or a fixed score)"),
+        )
+        .arg(
+            Arg::new("score-weight")
+                .long("score-weight")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|s| match ScoreWeights::default().set(s) {
+                    Some(_) => Ok(()),
+                    None => Err("expected KEY=VALUE with KEY one of frequency, news1, news2, ichi1, ichi2, spec1, spec2, gai1, gai2, common, nf, irregular, rare, outdated, search-only"),
+                })
+                .help("Override a weight of the term score, e.g. `news1=20` or `search-only=100`"),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .takes_value(true)
+                .help("Only write the JMdict entries matching an expression, e.g. `(pri:news1 or pri:ichi1) and not misc:vulg`, `pos:v5*`, `field:med` or `seq in FILE`"),
+        )
+        .arg(
+            Arg::new("form-policy")
+                .long("form-policy")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|s| match FormPolicies::default().set(s) {
+                    Some(_) => Ok(()),
+                    None => Err("expected CODE=POLICY with CODE one of sK, sk, iK, ik, io, oK, ok, rK, rk and POLICY one of drop, hide, penalize"),
+                })
+                .help("What to do with the forms marked with a ke_inf/re_inf code, e.g. `iK=drop` or `rK=hide` (search-only forms are hidden and the others penalized by default)"),
+        )
+        .arg(
+            Arg::new("pitch-accent")
+                .long("pitch-accent")
+                .takes_value(true)
+                .help("Tab-separated pitch accent file (term, reading, downstep positions), adds `pitch` term_meta rows"),
+        )
+        .arg(
+            Arg::new("structured-content")
+                .long("structured-content")
+                .help("Write glossaries as structured content instead of plain text"),
+        )
+        .arg(
+            Arg::new("max-examples")
+                .long("max-examples")
+                .takes_value(true)
+                .default_value("3")
+                .validator(|s| s.parse::<usize>())
+                .help("Example sentences shown under each sense of a structured-content glossary (needs JMdict_e_examp)"),
+        )
+        .arg(
+            Arg::new("furigana")
+                .long("furigana")
+                .takes_value(true)
+                .help("JmdictFurigana text file (`term|reading|0:ruby;1:ruby`), forms it lacks are aligned by the built-in aligner"),
+        )
+        .arg(
+            Arg::new("forms-table")
+                .long("forms-table")
+                .requires("structured-content")
+                .help("Add a table of every spelling and reading, with irregular, rare, outdated and search-only forms marked"),
+        )
+        .arg(
+            Arg::new("forms-dictionary")
+                .long("forms-dictionary")
+                .help("Also build a companion dictionary listing the other spellings and readings of each headword"),
+        )
+        .arg(
+            Arg::new("jmdict-simplified")
+                .long("jmdict-simplified")
+                .help("Also write every entry as jmdict-simplified JSON (`jmdict-eng.json`), without language sources, gloss types and genders, or dictionary revisions"),
+        )
+        .args(sqlite_args())
+        .arg(
+            Arg::new("stardict")
+                .long("stardict")
+                .help("Also write a StarDict dictionary (`JMdict_stardict/`) for GoldenDict or KOReader, looked up by any spelling or reading"),
+        )
+        .arg(
+            Arg::new("stardict-uncompressed")
+                .long("stardict-uncompressed")
+                .requires("stardict")
+                .help("Write the StarDict glossaries as a plain `.dict` instead of a dictzipped `.dict.dz`"),
+        )
+        .arg(
+            Arg::new("frequency-dictionary")
+                .long("frequency-dictionary")
+                .requires("frequency")
+                .help("Also build a separate frequency dictionary (term_meta_bank) from the frequency lists"),
+        )
+        .arg(
+            Arg::new("index-config")
+                .long("index-config")
+                .takes_value(true)
+                .help("JSON file with `index.json` fields (title, author, url, description, attribution, sourceLanguage, targetLanguage, frequencyMode)"),
+        )
+        .arg(Arg::new("title").long("title").takes_value(true).help("Dictionary title, must be unique in a Yomichan install"))
+        .arg(Arg::new("author").long("author").takes_value(true).help("Dictionary author"))
+        .arg(Arg::new("url").long("url").takes_value(true).help("Dictionary homepage"))
+        .arg(Arg::new("description").long("description").takes_value(true).help("Dictionary description"))
+        .arg(Arg::new("attribution").long("attribution").takes_value(true).help("Attribution text (defaults to the EDRDG licence statement)"))
+        .arg(Arg::new("source-language").long("source-language").takes_value(true).help("Language of the terms (default: ja)"))
+        .arg(Arg::new("target-language").long("target-language").takes_value(true).help("Language of the definitions (default: en)"))
+        .arg(
+            Arg::new("frequency-mode")
+                .long("frequency-mode")
+                .takes_value(true)
+                .possible_values(["rank-based", "occurrence-based"])
+                .help("Values of the frequency dictionary: rank of the form or its popularity (default: rank-based)"),
+        )
         .arg(
             Arg::new("log")
                 .long("log")
                 .takes_value(true)
+                .global(true)
                 .help("Also log output to file (for debugging)"),
         )
         .arg(
@@ -123,6 +763,7 @@ fn main() -> Result<()> {
                 .short('v')
                 .long("verbose")
                 .multiple_occurrences(true)
+                .global(true)
                 .help("Sets the level of debug information verbosity"),
         )
         .get_matches();
@@ -140,28 +781,20 @@ fn main() -> Result<()> {
         let file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(false)
             .open(log_path.unwrap())
             .unwrap();
         file.lock_exclusive()?;
     }
 
     debug!("-----Logger is initialized. Starting main program!-----");
-    let raw_freq_input = std::fs::read_to_string("japanese-word-frequency/frequency.txt")?;
-    let (_, vec_word_freq) =
-        word_frequency::parser::parse_frequency_input(&raw_freq_input.as_bytes()).unwrap();
-
-    let input_path = matches.value_of("input").unwrap();
-    let jmdict_xml = std::fs::read_to_string(input_path)?;
-    let _ = jmdict_xml::process_jmdict(&jmdict_xml, &vec_word_freq);
-
-    //let mut index_file = OpenOptions::new()
-    //    .create(true)
-    //    .write(true)
-    //    .truncate(true)
-    //    .open("index.json")?;
-
-    //write!(index_file, "{}", DictIndex::default_serialize())?;
-    //info!("Successfully wrote `index.json` file!");
+    match matches.subcommand_matches("validate") {
+        Some(validate_matches) => check_dictionary(
+            &Validator::new()?,
+            Path::new(validate_matches.value_of("path").unwrap()),
+        )?,
+        None => build_dictionaries(&matches)?,
+    }
 
     debug!("-----Everything is finished!-----");
     if lock {
@@ -169,8 +802,9 @@ fn main() -> Result<()> {
             .write(true)
             .open(log_path.unwrap())
             .unwrap();
-        file.unlock()?;
+        // the fs2 unlock, `File::unlock` of std only exists since Rust 1.89
+        FileExt::unlock(&file)?;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,193 @@
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::yomichan::Definition;
+
+/// Downstep positions of one term[reading], as listed in a Kanjium-style accent file.
+#[derive(Debug, PartialEq)]
+pub struct PitchAccent {
+    pub term: String,
+    pub reading: String,
+    // 0 is heiban, n the mora after which the pitch drops
+    pub positions: Vec<u8>,
+}
+
+// `明白<TAB>めいはく<TAB>0`, a kana term may leave the reading empty
+// and positions may carry a part of speech, e.g. `(名)0,(副)3`
+fn parse_line(line: &str) -> Result<PitchAccent> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    if fields.len() != 3 {
+        bail!("expected `term<TAB>reading<TAB>positions`");
+    }
+    let term = fields[0].trim().to_string();
+    let reading = match fields[1].trim() {
+        "" => term.clone(),
+        reading => reading.to_string(),
+    };
+    let mut positions = Vec::new();
+    for position in fields[2].split(',') {
+        let position = match position.trim().rsplit_once(')') {
+            Some((_part_of_speech, position)) => position,
+            None => position.trim(),
+        };
+        let position = position
+            .parse::<u8>()
+            .with_context(|| format!("invalid downstep position `{}`", position))?;
+        if !positions.contains(&position) {
+            positions.push(position);
+        }
+    }
+    Ok(PitchAccent {
+        term,
+        reading,
+        positions,
+    })
+}
+
+pub fn read_pitch_file(path: &str) -> Result<Vec<PitchAccent>> {
+    let raw_pitch_input = std::fs::read_to_string(path)?;
+    raw_pitch_input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_line(line)
+                .with_context(|| format!("Could not parse line {} of pitch file `{}`", i + 1, path))
+        })
+        .collect()
+}
+
+/// Pitch accents waiting to be linked to the forms of the JMdict entries.
+/// Each term[reading] gets one `pitch` row, even when several entries share the form.
+#[derive(Debug, Default)]
+pub struct PitchAccents {
+    accents: HashMap<(String, String), Vec<u8>>,
+    matched: HashSet<(String, String)>,
+}
+
+impl PitchAccents {
+    pub fn new(vec_pitch: Vec<PitchAccent>) -> Self {
+        let mut accents = HashMap::new();
+        for pitch in vec_pitch {
+            let positions: &mut Vec<u8> = accents.entry((pitch.term, pitch.reading)).or_default();
+            for position in pitch.positions {
+                if !positions.contains(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+        PitchAccents {
+            accents,
+            matched: HashSet::new(),
+        }
+    }
+
+    //["明白","pitch",{"pitches":[{"position":0}],"reading":"めいはく"}]
+    pub fn serialize_matches(&mut self, definition: &Definition) -> Vec<String> {
+        let mut ret = Vec::new();
+        for (term, reading) in definition.forms() {
+            let key = (term.to_string(), reading.to_string());
+            if self.matched.contains(&key) {
+                continue;
+            }
+            if let Some(positions) = self.accents.get(&key) {
+                let pitches = positions
+                    .iter()
+                    .map(|position| json!({ "position": position }))
+                    .collect::<Vec<_>>();
+                ret.push(
+                    json!([term, "pitch", {"reading": reading, "pitches": pitches}]).to_string(),
+                );
+                self.matched.insert(key);
+            }
+        }
+        ret
+    }
+
+    // term[reading] of the accents no JMdict form matched, sorted
+    pub fn unmatched(&self) -> Vec<String> {
+        let mut unmatched = self
+            .accents
+            .keys()
+            .filter(|key| !self.matched.contains(*key))
+            .map(|(term, reading)| format!("{}[{}]", term, reading))
+            .collect::<Vec<String>>();
+        unmatched.sort();
+        unmatched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_sample_file() {
+        let vec_pitch = read_pitch_file("tests/pitch-sample.txt").unwrap();
+        assert_eq!(vec_pitch.len(), 6);
+        assert_eq!(
+            vec_pitch[2],
+            PitchAccent {
+                term: "あしらう".to_string(),
+                reading: "あしらう".to_string(),
+                positions: vec![3],
+            }
+        );
+        assert_eq!(vec_pitch[3].positions, [2, 0]);
+        assert_eq!(vec_pitch[4].positions, [0]);
+        assert!(parse_line("明白\tめいはく\t-1").is_err());
+        assert!(parse_line("明白 めいはく 0").is_err());
+    }
+
+    #[test]
+    fn match_definition_forms() {
+        let mut pitch_accents =
+            PitchAccents::new(read_pitch_file("tests/pitch-sample.txt").unwrap());
+        let mut definition = Definition::default();
+        definition.add_term("遇う".to_string());
+        definition.add_term("配う".to_string());
+        definition.add_reading("あしらう".to_string());
+
+        assert_eq!(
+            pitch_accents.serialize_matches(&definition),
+            [
+                r#"["遇う","pitch",{"pitches":[{"position":3}],"reading":"あしらう"}]"#,
+                r#"["あしらう","pitch",{"pitches":[{"position":3}],"reading":"あしらう"}]"#,
+            ]
+        );
+        // a form shared by another entry only gets one row
+        assert!(pitch_accents.serialize_matches(&definition).is_empty());
+        assert_eq!(
+            pitch_accents.unmatched(),
+            [
+                "アセビ[アセビ]",
+                "存在[そんざいしない]",
+                "明白[めいはく]",
+                "馬酔木[あせび]"
+            ]
+        );
+    }
+
+    #[test]
+    fn match_only_existing_forms() {
+        let mut pitch_accents = PitchAccents::new(vec![
+            parse_line("馬酔木\tあせび\t0").unwrap(),
+            parse_line("馬酔木\tアセビ\t0").unwrap(),
+        ]);
+        let mut definition = Definition::default();
+        definition.add_term("馬酔木".to_string());
+        definition.add_reading("あせび".to_string());
+        definition
+            .add_reading("アセビ".to_string())
+            .set_reading_nokanji();
+
+        // アセビ does not read 馬酔木
+        assert_eq!(
+            pitch_accents.serialize_matches(&definition),
+            [r#"["馬酔木","pitch",{"pitches":[{"position":0}],"reading":"あせび"}]"#]
+        );
+        assert_eq!(pitch_accents.unmatched(), ["馬酔木[アセビ]"]);
+    }
+}
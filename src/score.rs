@@ -0,0 +1,183 @@
+use crate::entry::COMMON_PRIORITY;
+
+/// Weights of the term row score.
+/// A row scores `frequency * popularity + priority bonus - form status penalty`, where the priority bonus
+/// comes from the `ke_pri`/`re_pri` codes of the form and the penalty from its `ke_inf`/`re_inf` codes.
+/// This lets common JMdict words outrank obscure ones even when no frequency list knows them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreWeights {
+    pub frequency: f32,
+    pub news1: f32,
+    pub news2: f32,
+    pub ichi1: f32,
+    pub ichi2: f32,
+    pub spec1: f32,
+    pub spec2: f32,
+    pub gai1: f32,
+    pub gai2: f32,
+    // forms an EDICT2 or jmdict-simplified source only marks as common, see `COMMON_PRIORITY`
+    pub common: f32,
+    // bonus of nf01, it decreases linearly down to nf48
+    pub nf: f32,
+    // iK, ik, io
+    pub irregular: f32,
+    // rK, rk
+    pub rare: f32,
+    // oK, ok
+    pub outdated: f32,
+    // sK, sk
+    pub search_only: f32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            frequency: 1f32,
+            news1: 10f32,
+            news2: 5f32,
+            ichi1: 10f32,
+            ichi2: 5f32,
+            spec1: 10f32,
+            spec2: 5f32,
+            gai1: 10f32,
+            gai2: 5f32,
+            common: 10f32,
+            nf: 10f32,
+            irregular: 20f32,
+            rare: 20f32,
+            outdated: 30f32,
+            search_only: 50f32,
+        }
+    }
+}
+
+impl ScoreWeights {
+    // set one weight from a `key=value` string given on the command line
+    pub fn set(&mut self, key_value: &str) -> Option<&mut Self> {
+        let (key, value) = key_value.split_once('=')?;
+        let value = value.trim().parse::<f32>().ok()?;
+        let weight = match key.trim() {
+            "frequency" => &mut self.frequency,
+            "news1" => &mut self.news1,
+            "news2" => &mut self.news2,
+            "ichi1" => &mut self.ichi1,
+            "ichi2" => &mut self.ichi2,
+            "spec1" => &mut self.spec1,
+            "spec2" => &mut self.spec2,
+            "gai1" => &mut self.gai1,
+            "gai2" => &mut self.gai2,
+            "common" => &mut self.common,
+            "nf" => &mut self.nf,
+            "irregular" => &mut self.irregular,
+            "rare" => &mut self.rare,
+            "outdated" => &mut self.outdated,
+            "search-only" => &mut self.search_only,
+            _ => return None,
+        };
+        *weight = value;
+        Some(self)
+    }
+
+    fn priority_bonus(&self, priority: &str) -> f32 {
+        match priority {
+            "news1" => self.news1,
+            "news2" => self.news2,
+            "ichi1" => self.ichi1,
+            "ichi2" => self.ichi2,
+            "spec1" => self.spec1,
+            "spec2" => self.spec2,
+            "gai1" => self.gai1,
+            "gai2" => self.gai2,
+            COMMON_PRIORITY => self.common,
+            nf if nf.starts_with("nf") => match nf[2..].parse::<u8>() {
+                Ok(rank @ 1..=48) => self.nf * (49 - rank) as f32 / 48f32,
+                _ => 0f32,
+            },
+            _ => 0f32,
+        }
+    }
+
+    fn status_penalty(&self, info: &str) -> f32 {
+        match info {
+            "iK" | "ik" | "io" => self.irregular,
+            "rK" | "rk" => self.rare,
+            "oK" | "ok" => self.outdated,
+            "sK" | "sk" => self.search_only,
+            _ => 0f32,
+        }
+    }
+
+    /// Score of a row from the popularity of its form and the codes of its kanji and reading elements.
+    /// A code shared by the kanji and the reading (e.g. both marked `news1`) only counts once.
+    pub fn score<'a>(
+        &self,
+        popularity: f32,
+        priority: impl IntoIterator<Item = &'a String>,
+        info: impl IntoIterator<Item = &'a String>,
+    ) -> f32 {
+        let mut priority = priority.into_iter().collect::<Vec<&String>>();
+        priority.sort();
+        priority.dedup();
+        let mut info = info.into_iter().collect::<Vec<&String>>();
+        info.sort();
+        info.dedup();
+
+        self.frequency * popularity
+            + priority
+                .into_iter()
+                .map(|x| self.priority_bonus(x))
+                .sum::<f32>()
+            - info
+                .into_iter()
+                .map(|x| self.status_penalty(x))
+                .sum::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn score_priority_and_status() {
+        let weights = ScoreWeights::default();
+        assert_eq!(weights.score(30f32, &codes(&[]), &codes(&[])), 30f32);
+        // news1 on both the kanji and the reading only counts once
+        assert_eq!(
+            weights.score(30f32, &codes(&["news1", "nf01", "news1"]), &codes(&["rK"])),
+            30f32 + 10f32 + 10f32 - 20f32
+        );
+        assert_eq!(
+            weights.score(0f32, &codes(&["nf48"]), &codes(&[])),
+            10f32 / 48f32
+        );
+        assert_eq!(
+            weights.score(0f32, &codes(&["nf99"]), &codes(&["sK", "ateji"])),
+            -50f32
+        );
+        assert_eq!(
+            weights.score(0f32, &codes(&[COMMON_PRIORITY]), &codes(&[])),
+            10f32
+        );
+    }
+
+    #[test]
+    fn set_weight() {
+        let mut weights = ScoreWeights::default();
+        weights.set("frequency=0.5").unwrap();
+        weights.set("search-only = 100").unwrap();
+        assert_eq!(weights.frequency, 0.5f32);
+        assert_eq!(weights.search_only, 100f32);
+        weights.set("common=2").unwrap();
+        assert_eq!(
+            weights.score(0f32, &codes(&[COMMON_PRIORITY]), &codes(&[])),
+            2f32
+        );
+        assert!(weights.set("news3=1").is_none());
+        assert!(weights.set("news1").is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{bail, Result};
+
+use std::str::FromStr;
+
+use crate::edict::{edict_created, EdictReader};
+use crate::entry::Entry;
+use crate::filter::Filter;
+use crate::form_policy::FormPolicies;
+use crate::jmdict_simplified::JmdictSimplifiedReader;
+use crate::jmdict_xml::{jmdict_created, JmdictReader};
+
+/// A dictionary file read as JMdict entries, one at a time.
+/// Every source feeds the same writers, so EDICT2 or jmdict-simplified files get the same
+/// scoring and serialization as JMdict XML.
+pub trait DictionarySource: Iterator<Item = Result<Entry>> {
+    /// The codes the source declares with their descriptions, e.g. the DTD entities of JMdict.
+    /// They may only be complete once the first entry was read.
+    fn tags(&self) -> &[(String, String)] {
+        &[]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Jmdict,
+    // the legacy one line per entry text format
+    Edict2,
+    // https://github.com/scriptin/jmdict-simplified
+    JmdictSimplified,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jmdict" => Ok(InputFormat::Jmdict),
+            "edict2" => Ok(InputFormat::Edict2),
+            "jmdict-simplified" => Ok(InputFormat::JmdictSimplified),
+            _ => bail!("unknown input format `{}`", s),
+        }
+    }
+}
+
+/// The entries of `text` read in `format`, with the creation date the file declares.
+pub fn open_source(
+    format: InputFormat,
+    text: &str,
+) -> Result<(Box<dyn DictionarySource + '_>, Option<String>)> {
+    Ok(match format {
+        InputFormat::Jmdict => (
+            Box::new(JmdictReader::new(text.as_bytes())),
+            jmdict_created(text),
+        ),
+        InputFormat::Edict2 => (
+            Box::new(EdictReader::new(text.as_bytes())),
+            edict_created(text),
+        ),
+        InputFormat::JmdictSimplified => {
+            let reader = JmdictSimplifiedReader::new(text.as_bytes())?;
+            let created = reader.dict_date().map(str::to_string);
+            (Box::new(reader), created)
+        }
+    })
+}
+
+/// The entries of `source` that match `filter`, without their forms with the drop policy.
+/// Wrapping the source rather than a writer gives every output format the same entries.
+pub struct FilteredSource<'a> {
+    source: &'a mut dyn DictionarySource,
+    filter: Option<&'a Filter>,
+    form_policies: &'a FormPolicies,
+    filtered_out: usize,
+}
+
+impl<'a> FilteredSource<'a> {
+    pub fn new(
+        source: &'a mut dyn DictionarySource,
+        filter: Option<&'a Filter>,
+        form_policies: &'a FormPolicies,
+    ) -> Self {
+        FilteredSource {
+            source,
+            filter,
+            form_policies,
+            filtered_out: 0,
+        }
+    }
+
+    // number of entries the filter left out so far
+    pub fn filtered_out(&self) -> usize {
+        self.filtered_out
+    }
+}
+
+impl Iterator for FilteredSource<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in &mut self.source {
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            // the filter also sees the codes of the forms that are dropped
+            if self.filter.is_some_and(|filter| !filter.matches(&entry)) {
+                self.filtered_out += 1;
+                continue;
+            }
+            self.form_policies.drop_forms(&mut entry);
+            return Some(Ok(entry));
+        }
+        None
+    }
+}
+
+impl DictionarySource for FilteredSource<'_> {
+    fn tags(&self) -> &[(String, String)] {
+        self.source.tags()
+    }
+}
@@ -0,0 +1,386 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+use std::path::Path;
+
+use crate::entry::Entry;
+use crate::word_frequency::stats::Popularity;
+use crate::writer::DictionaryWriter;
+
+// positions are 1-based like the sense numbers of JMdict cross-references
+const SCHEMA: &str = "
+CREATE TABLE tag (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL
+);
+CREATE TABLE entry (
+    id INTEGER PRIMARY KEY, -- ent_seq
+    popularity REAL NOT NULL
+);
+CREATE TABLE form (
+    id INTEGER PRIMARY KEY,
+    entry_id INTEGER NOT NULL REFERENCES entry(id),
+    kind TEXT NOT NULL, -- kanji or reading
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    no_kanji INTEGER NOT NULL
+);
+CREATE TABLE form_tag (
+    form_id INTEGER NOT NULL REFERENCES form(id),
+    kind TEXT NOT NULL, -- info or priority
+    tag TEXT NOT NULL
+);
+CREATE TABLE form_restriction (
+    form_id INTEGER NOT NULL REFERENCES form(id),
+    kanji TEXT NOT NULL
+);
+CREATE TABLE sense (
+    id INTEGER PRIMARY KEY,
+    entry_id INTEGER NOT NULL REFERENCES entry(id),
+    position INTEGER NOT NULL
+);
+CREATE TABLE sense_restriction (
+    sense_id INTEGER NOT NULL REFERENCES sense(id),
+    kind TEXT NOT NULL, -- kanji or reading
+    form TEXT NOT NULL
+);
+CREATE TABLE sense_tag (
+    sense_id INTEGER NOT NULL REFERENCES sense(id),
+    kind TEXT NOT NULL, -- pos, field, misc, dialect or name_type
+    tag TEXT NOT NULL
+);
+CREATE TABLE sense_info (
+    sense_id INTEGER NOT NULL REFERENCES sense(id),
+    text TEXT NOT NULL
+);
+CREATE TABLE gloss (
+    sense_id INTEGER NOT NULL REFERENCES sense(id),
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE cross_ref (
+    sense_id INTEGER NOT NULL REFERENCES sense(id),
+    kind TEXT NOT NULL, -- xref or ant
+    text TEXT NOT NULL,
+    reading TEXT,
+    sense_number INTEGER
+);
+CREATE TABLE frequency (
+    entry_id INTEGER NOT NULL REFERENCES entry(id),
+    term TEXT NOT NULL,
+    reading TEXT NOT NULL, -- empty for a kana term
+    popularity REAL NOT NULL
+);
+";
+
+// created once every row is in, which is quicker than updating them on every insert
+const INDEXES: &str = "
+CREATE INDEX form_text ON form(text);
+CREATE INDEX form_entry ON form(entry_id);
+CREATE INDEX form_tag_form ON form_tag(form_id);
+CREATE INDEX form_restriction_form ON form_restriction(form_id);
+CREATE INDEX sense_entry ON sense(entry_id);
+CREATE INDEX sense_restriction_sense ON sense_restriction(sense_id);
+CREATE INDEX sense_tag_sense ON sense_tag(sense_id);
+CREATE INDEX sense_tag_tag ON sense_tag(tag);
+CREATE INDEX sense_info_sense ON sense_info(sense_id);
+CREATE INDEX gloss_sense ON gloss(sense_id);
+CREATE INDEX cross_ref_sense ON cross_ref(sense_id);
+CREATE INDEX frequency_term ON frequency(term, reading);
+";
+
+/// Writes the entries into a normalized SQLite database, with the popularity of the frequency lists.
+/// Every row is written in one transaction, committed by `finish`.
+pub struct SqliteWriter<'a> {
+    connection: Connection,
+    popularity: &'a Popularity,
+}
+
+impl<'a> SqliteWriter<'a> {
+    pub fn new(connection: Connection, popularity: &'a Popularity) -> Self {
+        SqliteWriter {
+            connection,
+            popularity,
+        }
+    }
+
+    // a database left by an earlier run is replaced, like the other outputs
+    pub fn create(path: &Path, popularity: &'a Popularity) -> Result<Self> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(SqliteWriter::new(Connection::open(path)?, popularity))
+    }
+
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+
+    fn insert_form_tags(&self, form_id: i64, kind: &str, tags: &[String]) -> Result<()> {
+        let mut insert = self
+            .connection
+            .prepare_cached("INSERT INTO form_tag (form_id, kind, tag) VALUES (?1, ?2, ?3)")?;
+        for tag in tags {
+            insert.execute(params![form_id, kind, tag])?;
+        }
+        Ok(())
+    }
+
+    fn insert_sense_restrictions(&self, sense_id: i64, kind: &str, forms: &[String]) -> Result<()> {
+        let mut insert = self.connection.prepare_cached(
+            "INSERT INTO sense_restriction (sense_id, kind, form) VALUES (?1, ?2, ?3)",
+        )?;
+        for form in forms {
+            insert.execute(params![sense_id, kind, form])?;
+        }
+        Ok(())
+    }
+
+    fn insert_sense_tags(&self, sense_id: i64, kind: &str, tags: &[String]) -> Result<()> {
+        let mut insert = self
+            .connection
+            .prepare_cached("INSERT INTO sense_tag (sense_id, kind, tag) VALUES (?1, ?2, ?3)")?;
+        for tag in tags {
+            insert.execute(params![sense_id, kind, tag])?;
+        }
+        Ok(())
+    }
+
+    fn insert_cross_refs(&self, sense_id: i64, kind: &str, xrefs: &[String]) -> Result<()> {
+        let mut insert = self.connection.prepare_cached(
+            "INSERT INTO cross_ref (sense_id, kind, text, reading, sense_number) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for xref in xrefs {
+            let (text, reading, sense_number) = split_xref(xref);
+            insert.execute(params![sense_id, kind, text, reading, sense_number])?;
+        }
+        Ok(())
+    }
+
+    // the popularity of every term[reading] pair of the entry a frequency list knows
+    fn insert_frequencies(&self, entry: &Entry) -> Result<()> {
+        let mut pairs = Vec::new();
+        for kanji in &entry.kanji {
+            for reading in &entry.readings {
+                if !reading.no_kanji
+                    && (reading.restrictions.is_empty()
+                        || reading.restrictions.contains(&kanji.text))
+                {
+                    pairs.push((kanji.text.as_str(), reading.text.as_str()));
+                }
+            }
+        }
+        pairs.extend(
+            entry
+                .readings
+                .iter()
+                .map(|reading| (reading.text.as_str(), "")),
+        );
+
+        let mut insert = self.connection.prepare_cached(
+            "INSERT INTO frequency (entry_id, term, reading, popularity) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (term, reading) in pairs {
+            if let Some(popularity) =
+                self.popularity
+                    .get_form_popularity(entry.ent_seq, term, reading)
+            {
+                insert.execute(params![entry.ent_seq, term, reading, popularity])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// `明白・めいはく・1` into its text, reading and sense number
+fn split_xref(xref: &str) -> (&str, Option<&str>, Option<u32>) {
+    let mut parts = xref.split('・').collect::<Vec<&str>>();
+    let sense_number = match parts.last().map(|part| part.parse::<u32>()) {
+        Some(Ok(sense_number)) if parts.len() > 1 => {
+            parts.pop();
+            Some(sense_number)
+        }
+        _ => None,
+    };
+    (parts[0], parts.get(1).copied(), sense_number)
+}
+
+impl DictionaryWriter for SqliteWriter<'_> {
+    fn begin(&mut self, tags: &[(String, String)]) -> Result<()> {
+        self.connection.execute_batch(SCHEMA)?;
+        self.connection.execute_batch("BEGIN")?;
+        let mut insert = self
+            .connection
+            .prepare_cached("INSERT OR REPLACE INTO tag (name, description) VALUES (?1, ?2)")?;
+        for (name, description) in tags {
+            insert.execute(params![name, description])?;
+        }
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.connection
+            .prepare_cached("INSERT INTO entry (id, popularity) VALUES (?1, ?2)")?
+            .execute(params![
+                entry.ent_seq,
+                self.popularity.get_popularity(entry.ent_seq)
+            ])?;
+
+        let forms = entry
+            .kanji
+            .iter()
+            .enumerate()
+            .map(|(i, form)| ("kanji", i, form))
+            .chain(
+                entry
+                    .readings
+                    .iter()
+                    .enumerate()
+                    .map(|(i, form)| ("reading", i, form)),
+            );
+        for (kind, i, form) in forms {
+            self.connection
+                .prepare_cached(
+                    "INSERT INTO form (entry_id, kind, position, text, no_kanji) VALUES (?1, ?2, ?3, ?4, ?5)",
+                )?
+                .execute(params![entry.ent_seq, kind, i + 1, form.text, form.no_kanji])?;
+            let form_id = self.connection.last_insert_rowid();
+            self.insert_form_tags(form_id, "info", &form.info)?;
+            self.insert_form_tags(form_id, "priority", &form.priority)?;
+            let mut insert = self
+                .connection
+                .prepare_cached("INSERT INTO form_restriction (form_id, kanji) VALUES (?1, ?2)")?;
+            for kanji in &form.restrictions {
+                insert.execute(params![form_id, kanji])?;
+            }
+        }
+
+        for (i, sense) in entry.senses.iter().enumerate() {
+            self.connection
+                .prepare_cached("INSERT INTO sense (entry_id, position) VALUES (?1, ?2)")?
+                .execute(params![entry.ent_seq, i + 1])?;
+            let sense_id = self.connection.last_insert_rowid();
+            self.insert_sense_restrictions(sense_id, "kanji", &sense.kanji_restrictions)?;
+            self.insert_sense_restrictions(sense_id, "reading", &sense.reading_restrictions)?;
+            self.insert_sense_tags(sense_id, "pos", &sense.pos)?;
+            self.insert_sense_tags(sense_id, "field", &sense.field)?;
+            self.insert_sense_tags(sense_id, "misc", &sense.misc)?;
+            self.insert_sense_tags(sense_id, "dialect", &sense.dialect)?;
+            self.insert_sense_tags(sense_id, "name_type", &sense.name_type)?;
+            let mut insert = self
+                .connection
+                .prepare_cached("INSERT INTO sense_info (sense_id, text) VALUES (?1, ?2)")?;
+            for info in &sense.info {
+                insert.execute(params![sense_id, info])?;
+            }
+            self.insert_cross_refs(sense_id, "xref", &sense.xrefs)?;
+            self.insert_cross_refs(sense_id, "ant", &sense.antonyms)?;
+            let mut insert = self.connection.prepare_cached(
+                "INSERT INTO gloss (sense_id, position, text) VALUES (?1, ?2, ?3)",
+            )?;
+            for (i, gloss) in sense.gloss.iter().enumerate() {
+                insert.execute(params![sense_id, i + 1, gloss])?;
+            }
+        }
+
+        self.insert_frequencies(entry)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.connection.execute_batch(INDEXES)?;
+        self.connection.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::jmdict_xml::JmdictReader;
+    use crate::word_frequency::parser::read_frequency_file;
+    use crate::word_frequency::stats::{MissingPopularity, Normalization};
+    use crate::writer::write_dictionary;
+
+    #[test]
+    fn write_sqlite_sample() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let popularity = Popularity::new(
+            &read_frequency_file("tests/frequency-sample.txt").unwrap(),
+            Normalization::Raw,
+            MissingPopularity::Min,
+        );
+        let mut writer = SqliteWriter::new(Connection::open_in_memory().unwrap(), &popularity);
+        write_dictionary(
+            &mut JmdictReader::new(jmdict_xml.as_bytes()),
+            &mut [&mut writer],
+        )
+        .unwrap();
+        let connection = writer.into_connection();
+
+        // looked up by a reading
+        let glosses = connection
+            .prepare(
+                "SELECT gloss.text FROM form
+                 JOIN sense ON sense.entry_id = form.entry_id
+                 JOIN gloss ON gloss.sense_id = sense.id
+                 WHERE form.text = 'あしらう' AND sense.position = 2
+                 ORDER BY gloss.position",
+            )
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(
+            glosses,
+            ["to arrange", "to decorate", "to dress", "to garnish"]
+        );
+
+        let priority: Vec<String> = connection
+            .prepare(
+                "SELECT tag FROM form_tag JOIN form ON form.id = form_tag.form_id
+                 WHERE form.text = '明白' AND form_tag.kind = 'priority'
+                 ORDER BY form_tag.rowid",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(priority, ["ichi1", "news1", "nf10"]);
+
+        let (no_kanji, description): (bool, String) = connection
+            .query_row(
+                "SELECT form.no_kanji, tag.description FROM form
+                 JOIN sense ON sense.entry_id = form.entry_id
+                 JOIN sense_tag ON sense_tag.sense_id = sense.id
+                 JOIN tag ON tag.name = sense_tag.tag
+                 WHERE form.text = 'アセビ' AND sense_tag.kind = 'misc'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(no_kanji);
+        assert_eq!(description, "word usually written using kana alone");
+
+        let popularity: f64 = connection
+            .query_row(
+                "SELECT popularity FROM frequency WHERE term = '配う' AND reading = 'あしらう'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(popularity, 36f64);
+    }
+
+    #[test]
+    fn split_cross_refs() {
+        assert_eq!(
+            split_xref("明白・めいはく・1"),
+            ("明白", Some("めいはく"), Some(1))
+        );
+        assert_eq!(split_xref("明白・2"), ("明白", None, Some(2)));
+        assert_eq!(split_xref("めいはく"), ("めいはく", None, None));
+    }
+}
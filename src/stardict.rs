@@ -0,0 +1,310 @@
+use anyhow::Result;
+use flate2::{Compress, Compression, Crc, FlushCompress, Status};
+
+use std::cmp::Ordering;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::entry::Entry;
+use crate::writer::DictionaryWriter;
+use crate::yomichan::{separated_revision_date, Definition, IndexMetadata, EDRDG_ATTRIBUTION};
+
+// the chunk size of the dictzip tool, a deflated chunk still fits in the u16 of its size
+const DICTZIP_CHUNK_LENGTH: usize = 58315;
+
+/// Writes a StarDict dictionary (`.ifo`, `.idx`, `.dict` or `.dict.dz`, and `.syn`).
+/// Each entry is stored once under its first spelling with the plain text glossary of Yomichan,
+/// every other spelling and reading of the entry is a synonym pointing to it.
+pub struct StardictWriter {
+    // the path of the files without their extension, e.g. `out/JMdict`
+    base_path: PathBuf,
+    bookname: String,
+    // `YYYY.MM.DD`
+    date: String,
+    description: String,
+    dictzip: bool,
+    dict: Vec<u8>,
+    // headword, offset and size of its glossary in `dict`
+    words: Vec<(String, u32, u32)>,
+    // synonym and the position of its headword in `words`
+    synonyms: Vec<(String, usize)>,
+}
+
+impl StardictWriter {
+    pub fn new(
+        base_path: &Path,
+        bookname: &str,
+        revision_date: &str,
+        metadata: &IndexMetadata,
+        dictzip: bool,
+    ) -> Result<Self> {
+        let attribution = metadata.attribution.as_deref().unwrap_or(EDRDG_ATTRIBUTION);
+        Ok(StardictWriter {
+            base_path: base_path.to_path_buf(),
+            bookname: bookname.to_string(),
+            date: separated_revision_date(revision_date, '.')?,
+            description: match &metadata.description {
+                Some(description) => format!("{}<br>{}", description, attribution),
+                None => attribution.to_string(),
+            },
+            dictzip,
+            dict: Vec::new(),
+            words: Vec::new(),
+            synonyms: Vec::new(),
+        })
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        let mut path = self.base_path.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    }
+}
+
+// the order StarDict looks words up in: ASCII case-insensitive first, then byte by byte
+fn stardict_cmp(a: &str, b: &str) -> Ordering {
+    a.bytes()
+        .map(|c| c.to_ascii_lowercase())
+        .cmp(b.bytes().map(|c| c.to_ascii_lowercase()))
+        .then_with(|| a.cmp(b))
+}
+
+// a gzip file whose chunks can be inflated on their own, the `RA` extra field lists their sizes
+fn dictzip(data: &[u8], chunk_length: usize) -> Result<Vec<u8>> {
+    let mut compress = Compress::new(Compression::best(), false);
+    let mut chunks = data.chunks(chunk_length).collect::<Vec<&[u8]>>();
+    // an empty file still needs its final deflate block
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+    let mut deflated = Vec::new();
+    let mut chunk_sizes = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        // a full flush ends each chunk on a byte boundary with an empty dictionary
+        let flush = match i + 1 == chunks.len() {
+            true => FlushCompress::Finish,
+            false => FlushCompress::Full,
+        };
+        let start = deflated.len();
+        let mut input = *chunk;
+        loop {
+            deflated.reserve(chunk_length);
+            let total_in = compress.total_in();
+            let status = compress.compress_vec(input, &mut deflated, flush)?;
+            input = &input[(compress.total_in() - total_in) as usize..];
+            let done = match flush {
+                FlushCompress::Finish => status == Status::StreamEnd,
+                _ => input.is_empty() && deflated.len() < deflated.capacity(),
+            };
+            if done {
+                break;
+            }
+        }
+        chunk_sizes.push(u16::try_from(deflated.len() - start)?);
+    }
+
+    let mut extra = Vec::new();
+    extra.extend_from_slice(b"RA");
+    extra.extend_from_slice(&u16::try_from(6 + 2 * chunk_sizes.len())?.to_le_bytes());
+    extra.extend_from_slice(&1u16.to_le_bytes());
+    extra.extend_from_slice(&u16::try_from(chunk_length)?.to_le_bytes());
+    extra.extend_from_slice(&u16::try_from(chunk_sizes.len())?.to_le_bytes());
+    for chunk_size in chunk_sizes {
+        extra.extend_from_slice(&chunk_size.to_le_bytes());
+    }
+
+    // no timestamp so the same input always gives the same file
+    let mut dz = vec![0x1f, 0x8b, 8, 0x04, 0, 0, 0, 0, 2, 3];
+    dz.extend_from_slice(&u16::try_from(extra.len())?.to_le_bytes());
+    dz.extend(extra);
+    dz.extend(deflated);
+    let mut crc = Crc::new();
+    crc.update(data);
+    dz.extend_from_slice(&crc.sum().to_le_bytes());
+    dz.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(dz)
+}
+
+impl DictionaryWriter for StardictWriter {
+    fn begin(&mut self, _tags: &[(String, String)]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let mut forms = entry
+            .kanji
+            .iter()
+            .chain(&entry.readings)
+            .map(|form| form.text.as_str())
+            .collect::<Vec<&str>>();
+        if forms.is_empty() {
+            return Ok(());
+        }
+        let glossary = Definition::from(entry).serialize_gloss();
+        self.words.push((
+            forms[0].to_string(),
+            u32::try_from(self.dict.len())?,
+            u32::try_from(glossary.len())?,
+        ));
+        self.dict.extend_from_slice(glossary.as_bytes());
+
+        let headword = forms.remove(0);
+        forms.sort_unstable();
+        forms.dedup();
+        for form in forms.into_iter().filter(|form| *form != headword) {
+            self.synonyms.push((form.to_string(), self.words.len() - 1));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut order = (0..self.words.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| stardict_cmp(&self.words[a].0, &self.words[b].0));
+        // position of every word in the sorted `.idx`, which is what `.syn` points to
+        let mut idx_positions = vec![0; self.words.len()];
+        let mut idx = Vec::new();
+        for (position, &i) in order.iter().enumerate() {
+            let (word, offset, size) = &self.words[i];
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&offset.to_be_bytes());
+            idx.extend_from_slice(&size.to_be_bytes());
+            idx_positions[i] = position;
+        }
+
+        self.synonyms.sort_by(|a, b| stardict_cmp(&a.0, &b.0));
+        let mut syn = Vec::new();
+        for (synonym, i) in &self.synonyms {
+            syn.extend_from_slice(synonym.as_bytes());
+            syn.push(0);
+            syn.extend_from_slice(&u32::try_from(idx_positions[*i])?.to_be_bytes());
+        }
+
+        let mut ifo = String::from("StarDict's dict ifo file\nversion=3.0.0\n");
+        writeln!(ifo, "bookname={}", self.bookname)?;
+        writeln!(ifo, "wordcount={}", self.words.len())?;
+        writeln!(ifo, "synwordcount={}", self.synonyms.len())?;
+        writeln!(ifo, "idxfilesize={}", idx.len())?;
+        // the values of `.ifo` are single lines
+        writeln!(
+            ifo,
+            "description={}",
+            self.description.replace('\n', "<br>")
+        )?;
+        writeln!(ifo, "date={}", self.date)?;
+        writeln!(ifo, "sametypesequence=m")?;
+
+        std::fs::write(self.path(".ifo"), ifo)?;
+        std::fs::write(self.path(".idx"), idx)?;
+        std::fs::write(self.path(".syn"), syn)?;
+        match self.dictzip {
+            true => std::fs::write(
+                self.path(".dict.dz"),
+                dictzip(&self.dict, DICTZIP_CHUNK_LENGTH)?,
+            )?,
+            false => std::fs::write(self.path(".dict"), &self.dict)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use flate2::{Decompress, FlushDecompress};
+    use std::io::Read;
+
+    use crate::jmdict_xml::JmdictReader;
+    use crate::writer::write_dictionary;
+
+    // the NUL terminated words of `.idx` or `.syn` with the u32 after them
+    fn read_words(data: &[u8], trailing: usize) -> Vec<(String, u32)> {
+        let mut words = Vec::new();
+        let mut rest = data;
+        while let Some(end) = rest.iter().position(|&c| c == 0) {
+            let word = String::from_utf8(rest[..end].to_vec()).unwrap();
+            let value = u32::from_be_bytes(rest[end + 1..end + 5].try_into().unwrap());
+            words.push((word, value));
+            rest = &rest[end + 1 + trailing..];
+        }
+        words
+    }
+
+    #[test]
+    fn write_stardict_sample() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let output_dir = std::env::temp_dir().join(format!("stardict-{}", std::process::id()));
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let mut writer = StardictWriter::new(
+            &output_dir.join("JMdict"),
+            "JMdict",
+            "20220207",
+            &IndexMetadata::default(),
+            false,
+        )
+        .unwrap();
+        write_dictionary(
+            &mut JmdictReader::new(jmdict_xml.as_bytes()),
+            &mut [&mut writer],
+        )
+        .unwrap();
+
+        let ifo = std::fs::read_to_string(output_dir.join("JMdict.ifo")).unwrap();
+        assert!(ifo.starts_with("StarDict's dict ifo file\nversion=3.0.0\nbookname=JMdict\n"));
+        assert!(ifo.contains("wordcount=3\nsynwordcount=8\n"));
+        assert!(ifo.contains("date=2022.02.07\n"));
+
+        let dict = std::fs::read(output_dir.join("JMdict.dict")).unwrap();
+        let idx = read_words(&std::fs::read(output_dir.join("JMdict.idx")).unwrap(), 8);
+        let words = idx
+            .iter()
+            .map(|(word, _)| word.as_str())
+            .collect::<Vec<&str>>();
+        // sorted by their UTF-8 bytes
+        assert_eq!(words, ["明白", "遇う", "馬酔木"]);
+
+        // a reading leads to the glossary of its entry
+        let syn = read_words(&std::fs::read(output_dir.join("JMdict.syn")).unwrap(), 4);
+        let (_, position) = syn.iter().find(|(word, _)| word == "アセビ").unwrap();
+        let offset = idx[*position as usize].1 as usize;
+        assert!(String::from_utf8_lossy(&dict[offset..])
+            .starts_with("あせび・あしび・あせぼ・あせぶ・アセビ【馬酔木】"));
+
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[test]
+    fn dictzip_chunks() {
+        let data = "明白 obvious; clear; plain\n".repeat(200);
+        let dz = dictzip(data.as_bytes(), 1000).unwrap();
+
+        let mut inflated = String::new();
+        GzDecoder::new(&dz[..])
+            .read_to_string(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, data);
+
+        // the second chunk inflates on its own
+        let chunk_count = u16::from_le_bytes([dz[20], dz[21]]) as usize;
+        assert_eq!(chunk_count, data.len().div_ceil(1000));
+        let first_size = u16::from_le_bytes([dz[22], dz[23]]) as usize;
+        let second_size = u16::from_le_bytes([dz[24], dz[25]]) as usize;
+        let start = 12 + 10 + 2 * chunk_count + first_size;
+        let mut second_chunk = Vec::with_capacity(1000);
+        Decompress::new(false)
+            .decompress_vec(
+                &dz[start..start + second_size],
+                &mut second_chunk,
+                FlushDecompress::Sync,
+            )
+            .unwrap();
+        assert_eq!(second_chunk, &data.as_bytes()[1000..2000]);
+
+        let mut inflated = Vec::new();
+        GzDecoder::new(&dictzip(&[], 1000).unwrap()[..])
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert!(inflated.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, bail, Context, Result};
+use jsonschema::JSONSchema;
+use log::{error, info};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+// schema of every file of a dictionary, keyed by the file name prefix (`term_bank_1.json`...)
+const SCHEMAS: [(&str, &str); 6] = [
+    (
+        "index.json",
+        include_str!("schemas/dictionary-index-schema.json"),
+    ),
+    (
+        "term_bank_",
+        include_str!("schemas/dictionary-term-bank-v3-schema.json"),
+    ),
+    (
+        "term_meta_bank_",
+        include_str!("schemas/dictionary-term-meta-bank-v3-schema.json"),
+    ),
+    (
+        "tag_bank_",
+        include_str!("schemas/dictionary-tag-bank-v3-schema.json"),
+    ),
+    (
+        "kanji_bank_",
+        include_str!("schemas/dictionary-kanji-bank-v3-schema.json"),
+    ),
+    (
+        "kanji_meta_bank_",
+        include_str!("schemas/dictionary-kanji-meta-bank-v3-schema.json"),
+    ),
+];
+
+/// A place where a dictionary file does not follow its Yomichan schema.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub file: String,
+    // index of the offending row of a bank, none for `index.json` or a file that is not valid JSON
+    pub row: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.row {
+            Some(row) => write!(f, "{} row {}: {}", self.file, row, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// The Yomichan dictionary schemas, compiled once and used for every file.
+pub struct Validator {
+    schemas: Vec<(&'static str, JSONSchema)>,
+}
+
+impl Validator {
+    pub fn new() -> Result<Self> {
+        let mut schemas = Vec::new();
+        for (prefix, schema) in SCHEMAS {
+            let schema: Value = serde_json::from_str(schema)?;
+            let compiled = JSONSchema::compile(&schema)
+                .map_err(|e| anyhow!("Could not compile the schema of `{}`: {}", prefix, e))?;
+            schemas.push((prefix, compiled));
+        }
+        Ok(Validator { schemas })
+    }
+
+    // files without a schema (images, styles...) are not checked
+    fn schema(&self, file_name: &str) -> Option<&JSONSchema> {
+        if !file_name.ends_with(".json") {
+            return None;
+        }
+        self.schemas
+            .iter()
+            .find(|(prefix, _)| file_name.starts_with(prefix))
+            .map(|(_, schema)| schema)
+    }
+
+    pub fn validate_file(&self, file_name: &str, contents: &str) -> Vec<Violation> {
+        let schema = match self.schema(file_name) {
+            Some(schema) => schema,
+            None => return Vec::new(),
+        };
+        let violation = |row: Option<usize>, message: String| Violation {
+            file: file_name.to_string(),
+            row,
+            message,
+        };
+        let instance: Value = match serde_json::from_str(contents) {
+            Ok(instance) => instance,
+            Err(e) => return vec![violation(None, format!("invalid JSON: {}", e))],
+        };
+        let errors = match schema.validate(&instance) {
+            Ok(()) => return Vec::new(),
+            Err(errors) => errors,
+        };
+
+        errors
+            .map(|error| {
+                let pointer = error.instance_path.to_string();
+                // banks are arrays of rows, so the first segment of the pointer is the row index
+                let row = if file_name == "index.json" {
+                    None
+                } else {
+                    pointer
+                        .split('/')
+                        .nth(1)
+                        .and_then(|row| row.parse::<usize>().ok())
+                };
+                let message = if pointer.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{} (at {})", error, pointer)
+                };
+                violation(row, message)
+            })
+            .collect()
+    }
+
+    fn validate_zip<R: Read + Seek>(&self, reader: R) -> Result<Vec<Violation>> {
+        let mut zip = ZipArchive::new(reader)?;
+        let mut violations = Vec::new();
+        let mut has_index = false;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let name = file.name().to_string();
+            if self.schema(&name).is_none() {
+                continue;
+            }
+            has_index |= name == "index.json";
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)
+                .with_context(|| format!("Could not read `{}`", name))?;
+            violations.extend(self.validate_file(&name, &contents));
+        }
+        if !has_index {
+            violations.push(missing_index());
+        }
+        Ok(violations)
+    }
+
+    fn validate_dir(&self, dir: &Path) -> Result<Vec<Violation>> {
+        let mut names = Vec::new();
+        for dir_entry in std::fs::read_dir(dir)? {
+            let dir_entry = dir_entry?;
+            if dir_entry.file_type()?.is_file() {
+                names.push(dir_entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+
+        let mut violations = Vec::new();
+        for name in names.iter().filter(|name| self.schema(name).is_some()) {
+            let contents = std::fs::read_to_string(dir.join(name))
+                .with_context(|| format!("Could not read `{}`", name))?;
+            violations.extend(self.validate_file(name, &contents));
+        }
+        if !names.iter().any(|name| name == "index.json") {
+            violations.push(missing_index());
+        }
+        Ok(violations)
+    }
+
+    /// Every violation of a dictionary, given as an unpacked directory or as a zip archive.
+    pub fn validate_path(&self, path: &Path) -> Result<Vec<Violation>> {
+        if path.is_dir() {
+            self.validate_dir(path)
+        } else {
+            let file =
+                File::open(path).with_context(|| format!("Could not open `{}`", path.display()))?;
+            self.validate_zip(file)
+        }
+    }
+}
+
+fn missing_index() -> Violation {
+    Violation {
+        file: "index.json".to_string(),
+        row: None,
+        message: "missing file".to_string(),
+    }
+}
+
+// logs every violation of the dictionary and fails if there is any
+pub fn check_dictionary(validator: &Validator, path: &Path) -> Result<()> {
+    let violations = validator.validate_path(path)?;
+    for violation in &violations {
+        error!("{}", violation);
+    }
+    if !violations.is_empty() {
+        bail!(
+            "`{}` has {} schema violation(s)",
+            path.display(),
+            violations.len()
+        );
+    }
+    info!("`{}` follows the Yomichan schemas", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::DictArchive;
+    use crate::yomichan::DictIndex;
+    use std::io::Cursor;
+
+    #[test]
+    fn validate_valid_archive() {
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        archive
+            .write_row(
+                "term",
+                r#"["明白","めいはく","","",98,["obvious"],1000220,""]"#,
+            )
+            .unwrap();
+        archive
+            .write_row("term_meta", r#"["明白","freq",{"reading":"めいはく","frequency":{"value":1,"displayValue":"1 (98)"}}]"#)
+            .unwrap();
+        let mut writer = archive
+            .finish(&DictIndex::new("JMdict", "20220207"))
+            .unwrap();
+        writer.set_position(0);
+
+        let validator = Validator::new().unwrap();
+        assert_eq!(validator.validate_zip(writer).unwrap(), []);
+    }
+
+    #[test]
+    fn validate_invalid_rows() {
+        let validator = Validator::new().unwrap();
+        let bank = r#"[["明白","めいはく","","",98,["obvious"],1000220,""],["遇う","あしらう","","v5","52",["to treat"],1000300,""],["配う","あしらう"]]"#;
+        let violations = validator.validate_file("term_bank_2.json", bank);
+        let rows = violations.iter().map(|x| x.row).collect::<Vec<_>>();
+        assert_eq!(rows, [Some(1), Some(2)]);
+        assert!(violations[0]
+            .to_string()
+            .starts_with("term_bank_2.json row 1: "));
+
+        let violations = validator.validate_file("tag_bank_1.json", "[");
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].row, None);
+
+        // only the dictionary files are checked
+        assert_eq!(validator.validate_file("styles.css", "{"), []);
+        assert_eq!(
+            validator
+                .validate_file("index.json", r#"{"title":"JMdict","revision":"1"}"#)
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{bail, Result};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::word_frequency::parser::WordFrequency;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    // sum(weight * popularity) / sum(weight) over the lists containing the form
+    WeightedMean,
+    // highest weight * popularity among the lists containing the form
+    Max,
+    // weighted mean of the form's relative rank in each list, scaled back to 0..=100
+    RankAverage,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "weighted-mean" => Ok(MergeStrategy::WeightedMean),
+            "max" => Ok(MergeStrategy::Max),
+            "rank-average" => Ok(MergeStrategy::RankAverage),
+            _ => bail!("unknown merge strategy `{}`", s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FrequencySource {
+    pub weight: f32,
+    pub words: Vec<WordFrequency>,
+}
+
+// a form is identified by its entry and the exact term[reading] pair of the frequency list
+type FormKey = (u32, String, String);
+
+// relative rank of every form in a list: 0 for the most popular form, close to 1 for the least
+fn relative_ranks(words: &[WordFrequency]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..words.len()).collect();
+    order.sort_by(|&a, &b| words[b].popularity.total_cmp(&words[a].popularity));
+
+    let mut ranks = vec![0f32; words.len()];
+    for (rank, index) in order.into_iter().enumerate() {
+        ranks[index] = rank as f32 / words.len() as f32;
+    }
+    ranks
+}
+
+/// Combines several frequency lists into one popularity per form.
+/// Forms missing from a list are left out of that list's contribution instead of counting as zero.
+/// The result keeps the ordering `get_popularity` relies on: grouped by `ent_seq`, most popular form first.
+pub fn merge_sources(sources: Vec<FrequencySource>, strategy: MergeStrategy) -> Vec<WordFrequency> {
+    let mut forms: Vec<FormKey> = Vec::new();
+    // (sum of weighted values, sum of weights) for the mean strategies, (max, _) for Max
+    let mut accumulated: HashMap<FormKey, (f32, f32)> = HashMap::new();
+
+    for source in sources {
+        let ranks = match strategy {
+            MergeStrategy::RankAverage => relative_ranks(&source.words),
+            _ => Vec::new(),
+        };
+        for (i, word) in source.words.into_iter().enumerate() {
+            let key = (word.ent_seq, word.term, word.reading);
+            let value = match strategy {
+                MergeStrategy::RankAverage => ranks[i],
+                _ => word.popularity,
+            };
+            match accumulated.get_mut(&key) {
+                Some((acc, weights)) => match strategy {
+                    MergeStrategy::Max => *acc = acc.max(source.weight * value),
+                    _ => {
+                        *acc += source.weight * value;
+                        *weights += source.weight;
+                    }
+                },
+                None => {
+                    accumulated.insert(key.clone(), (source.weight * value, source.weight));
+                    forms.push(key);
+                }
+            }
+        }
+    }
+
+    let mut merged = forms
+        .into_iter()
+        .map(|key| {
+            let (acc, weights) = accumulated[&key];
+            let popularity = match strategy {
+                MergeStrategy::WeightedMean if weights > 0f32 => acc / weights,
+                MergeStrategy::RankAverage if weights > 0f32 => 100f32 * (1f32 - acc / weights),
+                MergeStrategy::Max => acc,
+                _ => 0f32,
+            };
+            let (ent_seq, term, reading) = key;
+            WordFrequency {
+                ent_seq,
+                popularity,
+                term,
+                reading,
+            }
+        })
+        .collect::<Vec<WordFrequency>>();
+
+    // stable sort: forms with equal popularity keep the order of the first list they appeared in
+    merged.sort_by(|a, b| {
+        a.ent_seq
+            .cmp(&b.ent_seq)
+            .then(b.popularity.total_cmp(&a.popularity))
+    });
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(ent_seq: u32, popularity: f32, term: &str, reading: &str) -> WordFrequency {
+        WordFrequency {
+            ent_seq,
+            popularity,
+            term: term.to_string(),
+            reading: reading.to_string(),
+        }
+    }
+
+    fn sources() -> Vec<FrequencySource> {
+        vec![
+            FrequencySource {
+                weight: 1f32,
+                words: vec![
+                    word(1000300, 40f32, "遇う", "あしらう"),
+                    word(1000300, 20f32, "配う", "あしらう"),
+                    word(1000220, 90f32, "明白", "めいはく"),
+                ],
+            },
+            FrequencySource {
+                weight: 3f32,
+                words: vec![
+                    word(1000300, 60f32, "配う", "あしらう"),
+                    word(1000220, 50f32, "明白", "めいはく"),
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn merge_weighted_mean() {
+        let merged = merge_sources(sources(), MergeStrategy::WeightedMean);
+        assert_eq!(merged.len(), 3);
+        assert_eq!((merged[0].ent_seq, merged[0].popularity), (1000220, 60f32));
+        // 配う is now more popular than 遇う so it must come first for its entry
+        assert_eq!(
+            (merged[1].term.as_str(), merged[1].popularity),
+            ("配う", 50f32)
+        );
+        assert_eq!(
+            (merged[2].term.as_str(), merged[2].popularity),
+            ("遇う", 40f32)
+        );
+    }
+
+    #[test]
+    fn merge_max() {
+        let merged = merge_sources(sources(), MergeStrategy::Max);
+        assert_eq!(merged[0].popularity, 150f32);
+        assert_eq!(
+            (merged[1].term.as_str(), merged[1].popularity),
+            ("配う", 180f32)
+        );
+        assert_eq!(
+            (merged[2].term.as_str(), merged[2].popularity),
+            ("遇う", 40f32)
+        );
+    }
+
+    #[test]
+    fn merge_rank_average() {
+        let merged = merge_sources(sources(), MergeStrategy::RankAverage);
+        // 明白 is first in the first list and second (of two) in the second one
+        assert_eq!(merged[0].popularity, 100f32 * (1f32 - (3f32 * 0.5) / 4f32));
+        assert_eq!(merged[2].term, "遇う");
+        assert_eq!(merged[2].popularity, 100f32 * (1f32 - 1f32 / 3f32));
+    }
+
+    #[test]
+    fn merge_strategy_from_str() {
+        assert_eq!(
+            "rank-average".parse::<MergeStrategy>().ok(),
+            Some(MergeStrategy::RankAverage)
+        );
+        assert!("median".parse::<MergeStrategy>().is_err());
+    }
+}
@@ -1,2 +1,3 @@
+pub mod merge;
 pub mod parser;
 pub mod stats;
@@ -6,6 +6,8 @@ use nom::multi::many_till;
 use nom::sequence::tuple;
 use nom::IResult;
 
+use anyhow::{anyhow, Result};
+
 use std::str::from_utf8;
 
 #[derive(Debug)]
@@ -51,6 +53,13 @@ pub fn parse_frequency_input(input: &[u8]) -> IResult<&[u8], Vec<WordFrequency>>
     Ok((_freq, vec_word_freq))
 }
 
+pub fn read_frequency_file(path: &str) -> Result<Vec<WordFrequency>> {
+    let raw_freq_input = std::fs::read_to_string(path)?;
+    let (_, vec_word_freq) = parse_frequency_input(raw_freq_input.as_bytes())
+        .map_err(|e| anyhow!("Could not parse frequency file `{}`: {:?}", path, e))?;
+    Ok(vec_word_freq)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,6 +70,13 @@ mod tests {
         let (_, _vec_word_freq) = parse_frequency_input(raw_freq_sample.as_bytes()).unwrap();
     }
 
+    #[test]
+    fn read_sample_file() {
+        let vec_word_freq = read_frequency_file("tests/frequency-sample.txt").unwrap();
+        assert_eq!(vec_word_freq.len(), 9);
+        assert!(read_frequency_file("tests/does-not-exist.txt").is_err());
+    }
+
     #[test]
     #[ignore]
     fn parse_full_input() {
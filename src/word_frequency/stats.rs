@@ -1,3 +1,8 @@
+use anyhow::{bail, Result};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
 use crate::word_frequency::parser::*;
 
 fn mean(data: &[f32]) -> Option<f32> {
@@ -15,7 +20,7 @@ fn std_deviation(data: &[f32]) -> Option<f32> {
             let variance = data
                 .iter()
                 .map(|value| {
-                    let diff = data_mean - (*value as f32);
+                    let diff = data_mean - *value;
 
                     diff * diff
                 })
@@ -28,7 +33,7 @@ fn std_deviation(data: &[f32]) -> Option<f32> {
     }
 }
 
-pub fn get_freq_stats(vec_word_freq: &Vec<WordFrequency>) -> (f32, f32) {
+pub fn get_freq_stats(vec_word_freq: &[WordFrequency]) -> (f32, f32) {
     let data = vec_word_freq
         .iter()
         .map(|x| x.popularity)
@@ -38,27 +43,271 @@ pub fn get_freq_stats(vec_word_freq: &Vec<WordFrequency>) -> (f32, f32) {
     (data_mean, data_std_deviation)
 }
 
-// this returns the first (which is also the highest) popularity for an input ent_seq
-// for other terms with the same ent_seq, we will simply reduce this popularity point
-pub fn get_popularity(ent_seq: u32, vec_word_freq: &Vec<WordFrequency>) -> f32 {
-    let (mean, std_deviation) = get_freq_stats(&vec_word_freq);
-    let default_mean = mean - std_deviation;
-    match vec_word_freq.into_iter().find(|&x| x.ent_seq == ent_seq) {
-        Some(word_freq) => word_freq.popularity,
-        None => default_mean,
+/// Popularity used for every term when no frequency list is loaded.
+/// All entries tie, so Yomichan orders them by its own rules (term length, then sequence).
+pub const NEUTRAL_POPULARITY: f32 = 0.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    // popularity exactly as written in the frequency list
+    Raw,
+    // T-score: 50 + 10 * (popularity - mean) / std_deviation
+    ZScore,
+    // share of forms less popular than this one, 0..100
+    Percentile,
+    // ln(1 + popularity) scaled so the most popular form gets 100
+    Log,
+    // number of forms + 1 - rank, so the most popular form gets the highest score
+    Rank,
+}
+
+impl FromStr for Normalization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "raw" => Ok(Normalization::Raw),
+            "z-score" => Ok(Normalization::ZScore),
+            "percentile" => Ok(Normalization::Percentile),
+            "log" => Ok(Normalization::Log),
+            "rank" => Ok(Normalization::Rank),
+            _ => bail!("unknown normalization `{}`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingPopularity {
+    // mean - std_deviation of the raw popularity, normalized like every other value
+    MeanMinusStdDev,
+    Mean,
+    // the least popular form of the lists
+    Min,
+    // used as the score as is, no normalization applied
+    Fixed(f32),
+}
+
+impl FromStr for MissingPopularity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mean-minus-std-dev" => Ok(MissingPopularity::MeanMinusStdDev),
+            "mean" => Ok(MissingPopularity::Mean),
+            "min" => Ok(MissingPopularity::Min),
+            fixed => match fixed.parse::<f32>() {
+                Ok(score) => Ok(MissingPopularity::Fixed(score)),
+                Err(_) => bail!("expected mean-minus-std-dev, mean, min or a number"),
+            },
+        }
+    }
+}
+
+/// Normalized popularity of every entry and form of the loaded frequency lists.
+/// Everything is computed once up front so looking up a term does not walk the lists again.
+#[derive(Debug)]
+pub struct Popularity {
+    // the first (which is also the highest) popularity of an ent_seq
+    entries: HashMap<u32, f32>,
+    forms: HashMap<(u32, String, String), f32>,
+    missing: f32,
+}
+
+impl Popularity {
+    pub fn new(
+        vec_word_freq: &[WordFrequency],
+        normalization: Normalization,
+        missing: MissingPopularity,
+    ) -> Self {
+        if vec_word_freq.is_empty() {
+            return Popularity {
+                entries: HashMap::new(),
+                forms: HashMap::new(),
+                missing: match missing {
+                    MissingPopularity::Fixed(value) => value,
+                    _ => NEUTRAL_POPULARITY,
+                },
+            };
+        }
+
+        let (mean, std_deviation) = get_freq_stats(vec_word_freq);
+        let mut sorted = vec_word_freq
+            .iter()
+            .map(|x| x.popularity)
+            .collect::<Vec<f32>>();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let max = sorted[sorted.len() - 1];
+
+        let normalize = |popularity: f32| -> f32 {
+            let count = sorted.len() as f32;
+            match normalization {
+                Normalization::Raw => popularity,
+                Normalization::ZScore if std_deviation > 0f32 => {
+                    50f32 + 10f32 * (popularity - mean) / std_deviation
+                }
+                Normalization::ZScore => 50f32,
+                Normalization::Percentile => {
+                    100f32 * sorted.partition_point(|&x| x < popularity) as f32 / count
+                }
+                Normalization::Log if max > 0f32 => {
+                    100f32 * popularity.max(0f32).ln_1p() / max.ln_1p()
+                }
+                Normalization::Log => 0f32,
+                Normalization::Rank => sorted.partition_point(|&x| x <= popularity) as f32,
+            }
+        };
+
+        let mut entries = HashMap::new();
+        let mut forms = HashMap::new();
+        for word_freq in vec_word_freq {
+            let popularity = normalize(word_freq.popularity);
+            entries.entry(word_freq.ent_seq).or_insert(popularity);
+            forms
+                .entry((
+                    word_freq.ent_seq,
+                    word_freq.term.clone(),
+                    word_freq.reading.clone(),
+                ))
+                .or_insert(popularity);
+        }
+
+        let missing = match missing {
+            MissingPopularity::MeanMinusStdDev => normalize(mean - std_deviation),
+            MissingPopularity::Mean => normalize(mean),
+            MissingPopularity::Min => normalize(sorted[0]),
+            MissingPopularity::Fixed(value) => value,
+        };
+
+        Popularity {
+            entries,
+            forms,
+            missing,
+        }
+    }
+
+    // this returns the first (which is also the highest) popularity for an input ent_seq
+    // for other terms with the same ent_seq, we will simply reduce this popularity point
+    pub fn get_popularity(&self, ent_seq: u32) -> f32 {
+        match self.entries.get(&ent_seq) {
+            Some(popularity) => *popularity,
+            None => self.missing,
+        }
+    }
+
+    // popularity of one exact term[reading] form, if any frequency list contains it
+    pub fn get_form_popularity(&self, ent_seq: u32, term: &str, reading: &str) -> Option<f32> {
+        self.forms
+            .get(&(ent_seq, term.to_string(), reading.to_string()))
+            .copied()
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::word_frequency::parser::parse_frequency_input;
 
-    #[test]
-    fn get_popularity_sample() {
+    fn sample_popularity(normalization: Normalization, missing: MissingPopularity) -> Popularity {
         let raw_freq_sample = std::fs::read_to_string("tests/frequency-sample.txt").unwrap();
         let (_, vec_word_freq) = parse_frequency_input(raw_freq_sample.as_bytes()).unwrap();
-        assert_eq!(get_popularity(1000310u32, &vec_word_freq), 36.9_f32);
-        assert_eq!(get_popularity(1000225u32, &vec_word_freq), 36.9_f32);
-        assert_eq!(get_popularity(1000300u32, &vec_word_freq), 52_f32);
+        Popularity::new(&vec_word_freq, normalization, missing)
+    }
+
+    #[test]
+    fn get_popularity_sample() {
+        let popularity = sample_popularity(Normalization::Raw, MissingPopularity::MeanMinusStdDev);
+        assert_eq!(popularity.get_popularity(1000310u32), 36.9_f32);
+        assert_eq!(popularity.get_popularity(1000225u32), 36.9_f32);
+        assert_eq!(popularity.get_popularity(1000300u32), 52_f32);
+    }
+
+    #[test]
+    fn get_form_popularity_sample() {
+        let popularity = sample_popularity(Normalization::Raw, MissingPopularity::MeanMinusStdDev);
+        assert_eq!(
+            popularity.get_form_popularity(1000300u32, "配う", "あしらう"),
+            Some(36_f32)
+        );
+        assert_eq!(
+            popularity.get_form_popularity(1000300u32, "あしらう", ""),
+            None
+        );
+    }
+
+    #[test]
+    fn get_popularity_without_frequency() {
+        let popularity =
+            Popularity::new(&[], Normalization::Raw, MissingPopularity::MeanMinusStdDev);
+        assert_eq!(popularity.get_popularity(1000300u32), NEUTRAL_POPULARITY);
+    }
+
+    #[test]
+    fn get_popularity_missing() {
+        let vec_word_freq = [36f32, 52f32, 98f32]
+            .iter()
+            .enumerate()
+            .map(|(i, &popularity)| WordFrequency {
+                ent_seq: i as u32,
+                popularity,
+                term: String::new(),
+                reading: String::new(),
+            })
+            .collect::<Vec<WordFrequency>>();
+        let (mean, std_deviation) = get_freq_stats(&vec_word_freq);
+
+        let popularity = Popularity::new(
+            &vec_word_freq,
+            Normalization::Raw,
+            MissingPopularity::MeanMinusStdDev,
+        );
+        assert_eq!(popularity.get_popularity(42u32), mean - std_deviation);
+        let popularity =
+            Popularity::new(&vec_word_freq, Normalization::Raw, MissingPopularity::Min);
+        assert_eq!(popularity.get_popularity(42u32), 36f32);
+        let popularity = Popularity::new(
+            &vec_word_freq,
+            Normalization::Rank,
+            MissingPopularity::Fixed(-1f32),
+        );
+        assert_eq!(popularity.get_popularity(42u32), -1f32);
+    }
+
+    #[test]
+    fn normalize_sample() {
+        let missing = MissingPopularity::MeanMinusStdDev;
+
+        let popularity = sample_popularity(Normalization::Percentile, missing);
+        assert_eq!(popularity.get_popularity(1000220u32), 100f32 * 8f32 / 9f32);
+        assert_eq!(popularity.get_popularity(1000300u32), 100f32 * 7f32 / 9f32);
+
+        let popularity = sample_popularity(Normalization::Rank, missing);
+        assert_eq!(popularity.get_popularity(1000220u32), 9f32);
+        assert_eq!(popularity.get_popularity(1000300u32), 8f32);
+        // 36.9 appears twice so both entries share the same rank
+        assert_eq!(
+            popularity.get_popularity(1000225u32),
+            popularity.get_popularity(1000310u32)
+        );
+
+        let popularity = sample_popularity(Normalization::Log, missing);
+        assert_eq!(popularity.get_popularity(1000220u32), 100f32);
+
+        let popularity = sample_popularity(Normalization::ZScore, missing);
+        assert!(popularity.get_popularity(1000220u32) > 50f32);
+        assert!(popularity.get_popularity(1000310u32) < 50f32);
+        assert!(popularity.get_popularity(42u32) < popularity.get_popularity(1000310u32));
+    }
+
+    #[test]
+    fn normalization_from_str() {
+        assert_eq!(
+            "z-score".parse::<Normalization>().ok(),
+            Some(Normalization::ZScore)
+        );
+        assert_eq!(
+            "12.5".parse::<MissingPopularity>().ok(),
+            Some(MissingPopularity::Fixed(12.5f32))
+        );
+        assert!("median".parse::<MissingPopularity>().is_err());
     }
 }
@@ -0,0 +1,372 @@
+use anyhow::Result;
+use log::debug;
+
+use std::io::{Seek, Write};
+
+use crate::archive::DictArchive;
+use crate::entry::Entry;
+use crate::form_policy::FormPolicies;
+use crate::jmdict_xml::Mode;
+use crate::pitch_accent::PitchAccents;
+use crate::score::ScoreWeights;
+use crate::source::DictionarySource;
+use crate::word_frequency::stats::Popularity;
+use crate::yomichan::{serialize_form_status_tags, Definition, GlossaryOptions};
+
+/// An output format of the parsed entries.
+/// `write_dictionary` calls `begin` once, `write_entry` for every entry and `finish` last.
+pub trait DictionaryWriter {
+    /// `tags` are the codes the source declares with their descriptions.
+    fn begin(&mut self, tags: &[(String, String)]) -> Result<()>;
+    fn write_entry(&mut self, entry: &Entry) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Reads every entry once and hands it to each of the writers in turn.
+pub fn write_dictionary(
+    source: &mut dyn DictionarySource,
+    writers: &mut [&mut dyn DictionaryWriter],
+) -> Result<()> {
+    // the DTD of JMdict comes before the first entry
+    let first_entry = source.next().transpose()?;
+    for writer in writers.iter_mut() {
+        writer.begin(source.tags())?;
+    }
+    for entry in first_entry.into_iter().map(Ok).chain(source) {
+        let entry = entry?;
+        for writer in writers.iter_mut() {
+            writer.write_entry(&entry)?;
+        }
+    }
+    for writer in writers.iter_mut() {
+        writer.finish()?;
+    }
+    Ok(())
+}
+
+/// Writes the term banks of a Yomichan dictionary, and its pitch accent and tag banks on `finish`.
+/// The archive is left open for the caller to add `index.json`.
+pub struct YomichanWriter<'a, W: Write + Seek> {
+    archive: &'a mut DictArchive<W>,
+    mode: Mode,
+    popularity: &'a Popularity,
+    weights: &'a ScoreWeights,
+    form_policies: &'a FormPolicies,
+    glossary_options: &'a GlossaryOptions,
+    // the forms and names dictionaries have no pitch accents
+    pitch_accents: Option<&'a mut PitchAccents>,
+    // written after the term bank, the archive writes one kind of bank at a time
+    pitch_rows: Vec<String>,
+    name_types: Vec<(String, String)>,
+}
+
+impl<'a, W: Write + Seek> YomichanWriter<'a, W> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        archive: &'a mut DictArchive<W>,
+        mode: Mode,
+        popularity: &'a Popularity,
+        weights: &'a ScoreWeights,
+        form_policies: &'a FormPolicies,
+        glossary_options: &'a GlossaryOptions,
+        pitch_accents: Option<&'a mut PitchAccents>,
+    ) -> Self {
+        YomichanWriter {
+            archive,
+            mode,
+            popularity,
+            weights,
+            form_policies,
+            glossary_options,
+            pitch_accents,
+            pitch_rows: Vec::new(),
+            name_types: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write + Seek> DictionaryWriter for YomichanWriter<'_, W> {
+    fn begin(&mut self, tags: &[(String, String)]) -> Result<()> {
+        if self.mode == Mode::Jmnedict {
+            self.name_types = tags.to_vec();
+        }
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let mut definition = Definition::from(entry);
+        // the dropped forms are already gone, see `FilteredSource`
+        definition.hide_forms(self.form_policies);
+        debug!("{:#?}", definition);
+        let rows = match self.mode {
+            Mode::Forms => definition.serialize_forms(),
+            _ => definition.serialize(self.popularity, self.weights, self.glossary_options),
+        };
+        for row in rows {
+            self.archive.write_row("term", &row)?;
+        }
+        if let Some(pitch_accents) = self.pitch_accents.as_mut() {
+            self.pitch_rows
+                .extend(pitch_accents.serialize_matches(&definition));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for row in self.pitch_rows.drain(..) {
+            self.archive.write_row("term_meta", &row)?;
+        }
+        if self.mode == Mode::Jmdict {
+            for row in serialize_form_status_tags() {
+                self.archive.write_row("tag", &row)?;
+            }
+        }
+        for (name_type, description) in &self.name_types {
+            self.archive.write_row(
+                "tag",
+                &format!(
+                    r#"[{},"name",0,{},0]"#,
+                    serde_json::to_string(name_type)?,
+                    serde_json::to_string(description)?
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    use crate::filter::Filter;
+    use crate::furigana::Furigana;
+    use crate::jmdict_xml::JmdictReader;
+    use crate::source::FilteredSource;
+    use crate::word_frequency::stats::{MissingPopularity, Normalization};
+    use crate::yomichan::DictIndex;
+
+    #[derive(Default)]
+    struct CountingWriter {
+        begun: bool,
+        entries: Vec<u32>,
+        finished: bool,
+    }
+
+    impl DictionaryWriter for CountingWriter {
+        fn begin(&mut self, _tags: &[(String, String)]) -> Result<()> {
+            self.begun = true;
+            Ok(())
+        }
+        fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+            assert!(self.begun && !self.finished);
+            self.entries.push(entry.ent_seq);
+            Ok(())
+        }
+        fn finish(&mut self) -> Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn one_parse_feeds_every_writer() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Min);
+        let (weights, form_policies) = (ScoreWeights::default(), FormPolicies::default());
+        let glossary_options = GlossaryOptions::default();
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        let mut yomichan = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmdict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            None,
+        );
+        let mut counting = CountingWriter::default();
+        write_dictionary(
+            &mut JmdictReader::new(jmdict_xml.as_bytes()),
+            &mut [&mut yomichan, &mut counting],
+        )
+        .unwrap();
+
+        assert!(counting.finished);
+        assert_eq!(counting.entries, [1000220, 1000300, 1000310]);
+        assert_eq!(archive.bank_count("term"), 1);
+        assert_eq!(archive.bank_count("tag"), 1);
+    }
+
+    #[test]
+    fn filter_applies_to_every_writer() {
+        let jmdict_xml = std::fs::read_to_string("tests/sample.xml").unwrap();
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Min);
+        let (weights, form_policies) = (ScoreWeights::default(), FormPolicies::default());
+        let glossary_options = GlossaryOptions::default();
+        let filter = Filter::parse("misc:uk").unwrap();
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        let mut yomichan = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmdict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            None,
+        );
+        let mut counting = CountingWriter::default();
+        let mut reader = JmdictReader::new(jmdict_xml.as_bytes());
+        let mut source = FilteredSource::new(&mut reader, Some(&filter), &form_policies);
+        write_dictionary(&mut source, &mut [&mut yomichan, &mut counting]).unwrap();
+        assert_eq!(source.filtered_out(), 1);
+
+        // 明白 has no uk sense, neither writer gets it
+        assert_eq!(counting.entries, [1000300, 1000310]);
+        let writer = archive
+            .finish(&DictIndex::new("JMdict", "20220207"))
+            .unwrap();
+        let mut term_bank = String::new();
+        ZipArchive::new(writer)
+            .unwrap()
+            .by_name("term_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut term_bank)
+            .unwrap();
+        let rows: Vec<Value> = serde_json::from_str(&term_bank).unwrap();
+        assert!(rows.iter().all(|row| row[6] != 1000220));
+        assert!(rows.iter().any(|row| row[6] == 1000310));
+    }
+
+    #[test]
+    fn write_jmnedict_sample() {
+        let jmnedict_xml = std::fs::read_to_string("tests/jmnedict-sample.xml").unwrap();
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Min);
+        let (weights, form_policies) = (ScoreWeights::default(), FormPolicies::default());
+        let glossary_options = GlossaryOptions::default();
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        let mut writer = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmnedict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            None,
+        );
+        write_dictionary(
+            &mut JmdictReader::new(jmnedict_xml.as_bytes()),
+            &mut [&mut writer],
+        )
+        .unwrap();
+        let writer = archive
+            .finish(&DictIndex::new("JMnedict", "20220207"))
+            .unwrap();
+        let mut zip = ZipArchive::new(writer).unwrap();
+
+        let mut term_bank = String::new();
+        zip.by_name("term_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut term_bank)
+            .unwrap();
+        let rows = term_bank.lines().collect::<Vec<&str>>();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(
+            rows[0],
+            r#"[["ゝ泉","こいずみ","surname","",0,["こいずみ【ゝ泉】\nKoizumi"],5000000,""],"#
+        );
+        // kana only names are looked up by their reading
+        assert_eq!(
+            rows[1],
+            r#"["あいこ","","fem","",0,["あいこ\nAiko"],5000037,""],"#
+        );
+        assert!(rows[2].contains(r#""place surname""#));
+        assert!(rows[2].contains(r#"1 〘place・surname〙 Ueno\n2 〘surname〙 Kamino"#));
+        // a reading of no kanji form keeps the name type of its entry
+        assert!(rows[5].starts_with(r#"["トーキョー","","place","",-1,"#));
+
+        let mut tag_bank = String::new();
+        zip.by_name("tag_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut tag_bank)
+            .unwrap();
+        assert!(tag_bank.contains(r#"["surname","name",0,"family or surname",0]"#));
+    }
+
+    #[test]
+    fn write_examples_sample() {
+        let jmdict_xml = std::fs::read_to_string("tests/examples-sample.xml").unwrap();
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Min);
+        let (weights, form_policies) = (ScoreWeights::default(), FormPolicies::default());
+        let glossary_options = GlossaryOptions {
+            structured_content: true,
+            max_examples: 1,
+            furigana: Furigana::from_file("tests/furigana-sample.txt").unwrap(),
+            forms_table: false,
+        };
+        let mut archive = DictArchive::new(Cursor::new(Vec::new()));
+        let mut writer = YomichanWriter::new(
+            &mut archive,
+            Mode::Jmdict,
+            &popularity,
+            &weights,
+            &form_policies,
+            &glossary_options,
+            None,
+        );
+        write_dictionary(
+            &mut JmdictReader::new(jmdict_xml.as_bytes()),
+            &mut [&mut writer],
+        )
+        .unwrap();
+        let writer = archive
+            .finish(&DictIndex::new("JMdict", "20220207"))
+            .unwrap();
+        let mut zip = ZipArchive::new(writer).unwrap();
+
+        let mut term_bank = String::new();
+        zip.by_name("term_bank_1.json")
+            .unwrap()
+            .read_to_string(&mut term_bank)
+            .unwrap();
+        let rows: Vec<Value> = serde_json::from_str(&term_bank).unwrap();
+        assert_eq!(
+            rows[0][5][0],
+            json!({
+                "type": "structured-content",
+                "content": [
+                    {"tag": "div", "data": {"content": "header"}, "content": [[
+                        {"tag": "ruby", "content": ["明", {"tag": "rt", "content": "めい"}]},
+                        {"tag": "ruby", "content": ["白", {"tag": "rt", "content": "はく"}]},
+                    ]]},
+                    {"tag": "ol", "content": [{"tag": "li", "content": [
+                        {"tag": "span", "data": {"content": "sense-tags"}, "content": "〘adj-na〙 "},
+                        "obvious; clear",
+                        // only the first example is kept
+                        {"tag": "ul", "data": {"content": "examples"}, "content": [{"tag": "li", "content": [
+                            {"tag": "div", "lang": "ja", "content": [
+                                "それは",
+                                {"tag": "span", "style": {"fontWeight": "bold"}, "content": [
+                                    {"tag": "ruby", "content": ["明", {"tag": "rt", "content": "めい"}]},
+                                    {"tag": "ruby", "content": ["白", {"tag": "rt", "content": "はく"}]},
+                                ]},
+                                "な事実だ。",
+                            ]},
+                            {"tag": "div", "lang": "en", "content": "That is an obvious fact."},
+                        ]}]},
+                    ]}]},
+                ],
+            })
+        );
+        // the example of the second sense stays under the second sense
+        let senses = &rows[1][5][0]["content"][1]["content"];
+        assert_eq!(senses[0]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            senses[1]["content"][1]["content"][0]["content"][1]["content"],
+            "The andromeda bloomed in the garden."
+        );
+    }
+}
@@ -1,111 +1,624 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
 use std::fmt::Write;
 
+use crate::entry::{Entry, Example};
+use crate::form_policy::{FormPolicies, FormPolicy, FORM_STATUS_CODES};
+use crate::furigana::Furigana;
+use crate::score::ScoreWeights;
 use crate::word_frequency::parser::WordFrequency;
-use crate::word_frequency::stats::get_popularity;
+use crate::word_frequency::stats::Popularity;
 
 pub const MAX_TERM_PER_BANK: u16 = 10000;
 
+/// Attribution required by the EDRDG licence when JMdict material is redistributed.
+pub const EDRDG_ATTRIBUTION: &str = "This publication has included material from the JMdict (EDICT, etc.) dictionary files in accordance with the licence provisions of the Electronic Dictionaries Research Group. See http://www.edrdg.org/";
+pub const KANJIDIC_ATTRIBUTION: &str = "This publication has included material from the KANJIDIC2 dictionary file in accordance with the licence provisions of the Electronic Dictionaries Research Group. See http://www.edrdg.org/";
+
+// quoted and escaped JSON string, glosses may contain `"` or `\`
+fn json_string(s: &str) -> String {
+    serde_json::to_string(s).expect("a string always serializes")
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DictIndex {
     title: String,
     format: u8,
     revision: String,
     sequenced: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribution: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_language: Option<String>,
+    // "rank-based" or "occurrence-based", only set for frequency dictionaries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_updatable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_url: Option<String>,
 }
 
-impl DictIndex {
-    fn serialize(&self) -> String {
-        format!(
-            r#"{{"title":"{}","format":{},"revision":"{}","sequenced":{}}}"#,
-            self.title, self.format, self.revision, self.sequenced
-        )
-    }
-    pub fn default_serialize() -> String {
-        let revision_time_format = time::format_description::parse("[year][month][day]")
-            .expect("Could not parse to YYYYMMDD");
+/// Date part (YYYYMMDD) of the dictionary revision, so building the same input twice gives the same index.
+/// It comes from the `JMdict created` comment of the input, then from `SOURCE_DATE_EPOCH`,
+/// and only falls back to the current date when neither is available.
+pub fn revision_date(
+    jmdict_created: Option<String>,
+    source_date_epoch: Option<String>,
+) -> Result<String> {
+    let revision_time_format =
+        time::format_description::parse("[year][month][day]").expect("Could not parse to YYYYMMDD");
+
+    let date = match (jmdict_created, source_date_epoch) {
+        // jmdict-simplified files may declare any `dictDate`
+        (Some(created), _) => {
+            let created_format = time::format_description::parse("[year]-[month]-[day]")
+                .expect("Could not parse to YYYY-MM-DD");
+            time::Date::parse(&created, &created_format)
+                .with_context(|| format!("Creation date `{}` is not YYYY-MM-DD", created))?
+        }
+        (None, Some(epoch)) => {
+            let epoch = epoch
+                .trim()
+                .parse::<i64>()
+                .context("SOURCE_DATE_EPOCH is not a number of seconds")?;
+            time::OffsetDateTime::from_unix_timestamp(epoch)?.date()
+        }
+        (None, None) => {
+            warn!(
+                "No `JMdict created` comment nor SOURCE_DATE_EPOCH, the revision uses today's date"
+            );
+            time::OffsetDateTime::now_utc().date()
+        }
+    };
+    Ok(date
+        .format(&revision_time_format)
+        .expect("Could not parse to YYYYMMDD"))
+}
 
-        let revision_date = time::OffsetDateTime::now_local()
-            .expect("Could not get local time")
-            .format(&revision_time_format)
-            .expect("Could not parse to YYYYMMDD");
+/// The YYYYMMDD revision date with its parts separated, e.g. `2022-02-07` or `2022.02.07`.
+pub fn separated_revision_date(revision_date: &str, separator: char) -> Result<String> {
+    let revision_time_format =
+        time::format_description::parse("[year][month][day]").expect("Could not parse to YYYYMMDD");
+    let date = time::Date::parse(revision_date, &revision_time_format)
+        .with_context(|| format!("Revision date `{}` is not YYYYMMDD", revision_date))?;
+    Ok(format!(
+        "{:04}{}{:02}{}{:02}",
+        date.year(),
+        separator,
+        u8::from(date.month()),
+        separator,
+        date.day()
+    ))
+}
 
-        let dict_index = DictIndex {
-            title: "JMdict".to_string(),
+/// Optional `index.json` fields, read from a config file (a partial `index.json`) and from CLI flags.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct IndexMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub url: Option<String>,
+    pub description: Option<String>,
+    pub attribution: Option<String>,
+    pub source_language: Option<String>,
+    pub target_language: Option<String>,
+    pub frequency_mode: Option<String>,
+}
+
+impl IndexMetadata {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let raw_config = std::fs::read_to_string(path)?;
+        serde_json::from_str(&raw_config)
+            .with_context(|| format!("Could not parse index config `{}`", path))
+    }
+}
+
+impl DictIndex {
+    pub fn serialize(&self) -> String {
+        serde_json::to_string(self).expect("Could not serialize dictionary index")
+    }
+    pub fn new(title: &str, revision_date: &str) -> Self {
+        DictIndex {
+            title: title.to_string(),
             format: 3u8,
             revision: format!("JMdict-{}", revision_date),
             sequenced: true,
-        };
+            author: None,
+            url: None,
+            description: None,
+            attribution: Some(EDRDG_ATTRIBUTION.to_string()),
+            source_language: Some("ja".to_string()),
+            target_language: Some("en".to_string()),
+            frequency_mode: None,
+            is_updatable: None,
+            index_url: None,
+            download_url: None,
+        }
+    }
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    // revision of a dictionary built from another EDRDG file than JMdict, e.g. `KANJIDIC2-20220204`
+    pub fn source(&mut self, source: &str, revision_date: &str, sequenced: bool) -> &mut Self {
+        self.revision = format!("{}-{}", source, revision_date);
+        self.sequenced = sequenced;
+        self
+    }
+    // lets Yomichan update the dictionary from `<base_url>/<name>/`, see `archive::archive_path`
+    pub fn updatable(&mut self, base_url: &str, name: &str) -> &mut Self {
+        let base_url = base_url.trim_end_matches('/');
+        self.is_updatable = Some(true);
+        self.index_url = Some(format!("{}/{}/index.json", base_url, name));
+        self.download_url = Some(format!("{}/{}/{}.zip", base_url, name, name));
+        self
+    }
+    // only the fields set in the metadata replace the defaults
+    pub fn metadata(&mut self, metadata: &IndexMetadata) -> &mut Self {
+        let fields = [
+            (&mut self.author, &metadata.author),
+            (&mut self.url, &metadata.url),
+            (&mut self.description, &metadata.description),
+            (&mut self.attribution, &metadata.attribution),
+            (&mut self.source_language, &metadata.source_language),
+            (&mut self.target_language, &metadata.target_language),
+            (&mut self.frequency_mode, &metadata.frequency_mode),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+        if let Some(title) = &metadata.title {
+            self.title = title.clone();
+        }
+        self
+    }
+}
+
+/// One `freq` row of a term_meta_bank.
+/// With the `rank-based` frequency mode the value is the rank of the form among all forms of the
+/// frequency lists (1 = most popular), with `occurrence-based` it is the popularity itself.
+#[derive(Debug, PartialEq)]
+pub struct FrequencyMeta {
+    term: String,
+    reading: String,
+    rank: usize,
+    popularity: f32,
+}
 
-        dict_index.serialize()
+impl FrequencyMeta {
+    // competition ranking: forms sharing the same popularity share the same rank.
+    // A term[reading] listed for several entries gets one row, with its highest popularity.
+    pub fn from_word_frequency(vec_word_freq: &[WordFrequency]) -> Vec<Self> {
+        let mut forms: Vec<(&str, &str, f32)> = Vec::new();
+        let mut positions: HashMap<(&str, &str), usize> = HashMap::new();
+        for word_freq in vec_word_freq {
+            let key = (word_freq.term.as_str(), word_freq.reading.as_str());
+            match positions.get(&key) {
+                Some(&i) => forms[i].2 = forms[i].2.max(word_freq.popularity),
+                None => {
+                    positions.insert(key, forms.len());
+                    forms.push((key.0, key.1, word_freq.popularity));
+                }
+            }
+        }
+        let mut sorted = forms
+            .iter()
+            .map(|&(_, _, popularity)| popularity)
+            .collect::<Vec<f32>>();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+
+        forms
+            .into_iter()
+            .map(|(term, reading, popularity)| FrequencyMeta {
+                term: term.to_string(),
+                reading: reading.to_string(),
+                rank: 1 + sorted.partition_point(|&x| x > popularity),
+                popularity,
+            })
+            .collect()
+    }
+
+    //["明白","freq",{"reading":"めいはく","frequency":{"value":1,"displayValue":"1 (98)"}}]
+    pub fn serialize(&self, frequency_mode: &str) -> String {
+        let value = match frequency_mode {
+            "occurrence-based" => self.popularity,
+            _ => self.rank as f32,
+        };
+        let frequency = format!(
+            r#"{{"value":{},"displayValue":"{} ({})"}}"#,
+            value, self.rank, self.popularity
+        );
+        // kana only forms have no reading in the frequency lists
+        if self.reading.is_empty() {
+            format!(r#"[{},"freq",{}]"#, json_string(&self.term), frequency)
+        } else {
+            format!(
+                r#"[{},"freq",{{"reading":{},"frequency":{}}}]"#,
+                json_string(&self.term),
+                json_string(&self.reading),
+                frequency
+            )
+        }
     }
 }
 
-enum Identifier {
-    Ichidan,
-    Godan,
+/// Tags of the kanji dictionary: the jōyō/jinmeiyō classes and the names of the kanji stats.
+pub const KANJI_TAGS: [&str; 7] = [
+    r#"["kyouiku","frequent",0,"Kyōiku kanji, taught in elementary school",0]"#,
+    r#"["jouyou","frequent",0,"Jōyō kanji, taught in secondary school",0]"#,
+    r#"["jinmeiyou","frequent",0,"Jinmeiyō kanji, used in names",0]"#,
+    r#"["strokes","misc",0,"Stroke count",0]"#,
+    r#"["grade","misc",0,"School grade",0]"#,
+    r#"["jlpt","misc",0,"Former JLPT level",0]"#,
+    r#"["freq","misc",0,"Frequency rank in newspapers",0]"#,
+];
+
+/// One `<character>` of KANJIDIC2.
+#[derive(Debug, Default, PartialEq)]
+pub struct Kanji {
+    literal: String,
+    // <reading r_type="ja_on">
+    onyomi: Vec<String>,
+    // <reading r_type="ja_kun">
+    kunyomi: Vec<String>,
+    // English <meaning> only
+    meanings: Vec<String>,
+    // the first <stroke_count> is the accepted one, the others are common miscounts
+    stroke_count: Option<u8>,
+    grade: Option<u8>,
+    jlpt: Option<u8>,
+    // rank among the 2500 most used kanji in newspapers
+    frequency: Option<u16>,
 }
 
-impl Identifier {
-    fn to_identifier(s: &str) -> &str {
-        match s {
-            "v1" | "v1-s" => "v1",
-            "v5aru" | "v5b" | "v5g" | "v5k" | "v5k-s" | "v5m" | "v5n" | "v5r" | "v5r-i" | "v5s"
-            | "v5t" | "v5u" | "v5u-s" | "v5uru" => "v5",
+impl Kanji {
+    pub fn literal(&mut self, literal: String) -> &mut Self {
+        self.literal = literal;
+        self
+    }
+    pub fn add_onyomi(&mut self, onyomi: String) -> &mut Self {
+        self.onyomi.push(onyomi);
+        self
+    }
+    pub fn add_kunyomi(&mut self, kunyomi: String) -> &mut Self {
+        self.kunyomi.push(kunyomi);
+        self
+    }
+    pub fn add_meaning(&mut self, meaning: String) -> &mut Self {
+        self.meanings.push(meaning);
+        self
+    }
+    pub fn stroke_count(&mut self, stroke_count: u8) -> &mut Self {
+        self.stroke_count.get_or_insert(stroke_count);
+        self
+    }
+    pub fn grade(&mut self, grade: u8) -> &mut Self {
+        self.grade = Some(grade);
+        self
+    }
+    pub fn jlpt(&mut self, jlpt: u8) -> &mut Self {
+        self.jlpt = Some(jlpt);
+        self
+    }
+    pub fn frequency(&mut self, frequency: u16) -> &mut Self {
+        self.frequency = Some(frequency);
+        self
+    }
+
+    // grades 1 to 6 are the kyōiku kanji, 8 the rest of the jōyō kanji and 9, 10 the jinmeiyō kanji
+    fn tags(&self) -> &str {
+        match self.grade {
+            Some(1..=6) => "kyouiku",
+            Some(8) => "jouyou",
+            Some(9 | 10) => "jinmeiyou",
             _ => "",
         }
     }
+
+    //["亜","ア","つ.ぐ","jouyou",["Asia","rank next","come after","-ous"],{"freq":"1509","grade":"8","jlpt":"1","strokes":"7"}]
+    pub fn serialize(&self) -> String {
+        let mut stats = serde_json::Map::new();
+        let values = [
+            ("strokes", self.stroke_count.map(u16::from)),
+            ("grade", self.grade.map(u16::from)),
+            ("jlpt", self.jlpt.map(u16::from)),
+            ("freq", self.frequency),
+        ];
+        for (key, value) in values {
+            if let Some(value) = value {
+                stats.insert(key.to_string(), value.to_string().into());
+            }
+        }
+        format!(
+            r#"[{},{},{},"{}",{},{}]"#,
+            json_string(&self.literal),
+            json_string(&self.onyomi.join(" ")),
+            json_string(&self.kunyomi.join(" ")),
+            self.tags(),
+            serde_json::to_string(&self.meanings).expect("strings always serialize"),
+            serde_json::Value::Object(stats),
+        )
+    }
+
+    //["亜","freq",1509]
+    pub fn serialize_meta(&self) -> Option<String> {
+        self.frequency
+            .map(|frequency| format!(r#"[{},"freq",{}]"#, json_string(&self.literal), frequency))
+    }
+}
+
+/// How the glossary of a term row is written.
+#[derive(Debug, Clone, Default)]
+pub struct GlossaryOptions {
+    // structured content instead of plain text, example sentences are only shown in structured content
+    pub structured_content: bool,
+    // cap of example sentences under each sense
+    pub max_examples: usize,
+    // ruby of the header and of the term in example sentences
+    pub furigana: Furigana,
+    // table of every spelling and reading of the entry, in structured content
+    pub forms_table: bool,
+}
+
+// values of the 1-based `sense`, a sense without values of its own stays empty
+// so that the values of every sense stay at its index
+fn add_to_sense<T: Clone>(senses: &mut Vec<Vec<T>>, value: T, sense: usize) {
+    if sense == 0 {
+        return;
+    }
+    if senses.len() < sense {
+        senses.resize(sense, Vec::new());
+    }
+    senses[sense - 1].push(value);
 }
 
-#[derive(Debug, Default)]
+// rows of forms with a status code score below every row of a form without one,
+// so a misspelling a frequency list happens to know never outranks the canonical spelling
+fn below_canonical(scores: &mut [f32], flagged: &[bool]) {
+    let floor = scores
+        .iter()
+        .zip(flagged)
+        .filter(|(_, &flagged)| !flagged)
+        .map(|(score, _)| *score)
+        .reduce(f32::min);
+    if let Some(floor) = floor {
+        for (score, _) in scores
+            .iter_mut()
+            .zip(flagged)
+            .filter(|(_, &flagged)| flagged)
+        {
+            *score = score.min(floor - 1f32);
+        }
+    }
+}
+
+// marker and description of the ke_inf/re_inf codes shown in the forms table
+fn form_status(code: &str) -> (&str, &str) {
+    match code {
+        "iK" => ("irr.", "irregular kanji usage"),
+        "ik" => ("irr.", "irregular kana usage"),
+        "io" => ("irr.", "irregular okurigana usage"),
+        "oK" => ("old", "outdated kanji usage"),
+        "ok" => ("old", "outdated kana usage"),
+        "rK" => ("rare", "rarely used kanji form"),
+        "rk" => ("rare", "rarely used kana form"),
+        "sK" => ("search", "search-only kanji form"),
+        "sk" => ("search", "search-only kana form"),
+        _ => (code, code),
+    }
+}
+
+//["iK","form",0,"irregular kanji usage",0]
+pub fn serialize_form_status_tags() -> Vec<String> {
+    FORM_STATUS_CODES
+        .iter()
+        .map(|code| {
+            format!(
+                r#"[{},"form",0,{},0]"#,
+                json_string(code),
+                json_string(form_status(code).1)
+            )
+        })
+        .collect()
+}
+
+impl Example {
+    // the Japanese sentence with the term in bold, `keyword` is the term as it should be shown
+    fn highlighted_sentence(&self, keyword: Value) -> Value {
+        match self.sentence.find(&self.text) {
+            Some(start) if !self.text.is_empty() => {
+                let end = start + self.text.len();
+                let mut content = Vec::new();
+                if start > 0 {
+                    content.push(json!(&self.sentence[..start]));
+                }
+                content.push(json!({
+                    "tag": "span",
+                    "style": {"fontWeight": "bold"},
+                    "content": keyword,
+                }));
+                if end < self.sentence.len() {
+                    content.push(json!(&self.sentence[end..]));
+                }
+                Value::Array(content)
+            }
+            _ => json!(self.sentence),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Definition {
     // <keb> AKA multiple kanji form/ or multiple readings
     term: Vec<String>,
     // <reb>
     reading: Vec<String>,
     pos: Vec<Vec<String>>,
-    uk: bool,
     gloss: Vec<Vec<String>>,
     misc: Vec<Vec<String>>,
+    // <field> of each sense
+    field: Vec<Vec<String>>,
     sequence_number: u32,
     number_of_sense: u16,
+    // <ke_inf>/<ke_pri> of each <keb>
+    term_info: Vec<Vec<String>>,
+    term_priority: Vec<Vec<String>>,
+    // <re_inf>/<re_pri> of each <reb>
+    reading_info: Vec<Vec<String>>,
+    reading_priority: Vec<Vec<String>>,
+    // <re_restr> of each <reb>, the only kanji forms it reads, and whether it is <re_nokanji>
+    reading_restriction: Vec<Vec<String>>,
+    reading_nokanji: Vec<bool>,
+    // forms left out of the headers by their form policy
+    hidden_forms: Vec<String>,
+    // <name_type> of each <trans>, only in JMnedict
+    name_type: Vec<Vec<String>>,
+    // <example> of each sense
+    examples: Vec<Vec<Example>>,
+}
+
+// the Yomichan model of an entry, each sense numbered from 1
+impl From<&Entry> for Definition {
+    fn from(entry: &Entry) -> Self {
+        let mut definition = Definition::default();
+        definition.sequence_number(entry.ent_seq);
+        for form in &entry.kanji {
+            definition.add_term(form.text.clone());
+            for info in &form.info {
+                definition.add_term_info(info.clone());
+            }
+            for priority in &form.priority {
+                definition.add_term_priority(priority.clone());
+            }
+        }
+        for form in &entry.readings {
+            definition.add_reading(form.text.clone());
+            for info in &form.info {
+                definition.add_reading_info(info.clone());
+            }
+            for priority in &form.priority {
+                definition.add_reading_priority(priority.clone());
+            }
+            for term in &form.restrictions {
+                definition.add_reading_restriction(term.clone());
+            }
+            if form.no_kanji {
+                definition.set_reading_nokanji();
+            }
+        }
+        for (i, sense) in entry.senses.iter().enumerate() {
+            let number = i + 1;
+            for pos in &sense.pos {
+                definition.add_pos(pos.clone(), number);
+            }
+            for field in &sense.field {
+                definition.add_field(field.clone(), number);
+            }
+            for misc in &sense.misc {
+                definition.add_misc(misc.clone(), number);
+            }
+            for name_type in &sense.name_type {
+                definition.add_name_type(name_type.clone(), number);
+            }
+            for gloss in &sense.gloss {
+                definition.add_gloss(gloss.clone(), number);
+            }
+            for example in &sense.examples {
+                definition.add_example(example.clone(), number);
+            }
+            definition.increase_sense();
+        }
+        definition
+    }
 }
 
 impl Definition {
     pub fn add_term(&mut self, term: String) -> &mut Self {
         self.term.push(term);
+        self.term_info.push(Vec::new());
+        self.term_priority.push(Vec::new());
         self
     }
     pub fn add_reading(&mut self, reading: String) -> &mut Self {
         self.reading.push(reading);
+        self.reading_info.push(Vec::new());
+        self.reading_priority.push(Vec::new());
+        self.reading_restriction.push(Vec::new());
+        self.reading_nokanji.push(false);
         self
     }
-    pub fn add_misc(&mut self, misc: String, sense: usize) -> &mut Self {
-        if self.misc.is_empty() {
-            self.misc = vec![vec![misc]];
-        } else if self.misc.len() == sense {
-            let mut sense = self.misc.pop().unwrap();
-            sense.push(misc);
-            self.misc.push(sense);
-        } else if self.misc.len() < sense {
-            let sense = vec![misc];
-            self.misc.push(sense);
+    // the info and priority codes belong to the last added term or reading
+    pub fn add_term_info(&mut self, info: String) -> &mut Self {
+        if let Some(term_info) = self.term_info.last_mut() {
+            term_info.push(info);
         }
         self
     }
-    pub fn add_pos(&mut self, pos: String, sense: usize) -> &mut Self {
-        if self.pos.is_empty() {
-            self.pos = vec![vec![pos]];
-        } else if self.pos.len() == sense {
-            let mut sense = self.pos.pop().unwrap();
-            sense.push(pos);
-            self.pos.push(sense);
-        } else if self.pos.len() < sense {
-            let sense = vec![pos];
-            self.pos.push(sense);
+    pub fn add_term_priority(&mut self, priority: String) -> &mut Self {
+        if let Some(term_priority) = self.term_priority.last_mut() {
+            term_priority.push(priority);
+        }
+        self
+    }
+    pub fn add_reading_info(&mut self, info: String) -> &mut Self {
+        if let Some(reading_info) = self.reading_info.last_mut() {
+            reading_info.push(info);
+        }
+        self
+    }
+    pub fn add_reading_priority(&mut self, priority: String) -> &mut Self {
+        if let Some(reading_priority) = self.reading_priority.last_mut() {
+            reading_priority.push(priority);
+        }
+        self
+    }
+    pub fn add_reading_restriction(&mut self, term: String) -> &mut Self {
+        if let Some(reading_restriction) = self.reading_restriction.last_mut() {
+            reading_restriction.push(term);
         }
         self
     }
-    pub fn set_uk(&mut self) -> &mut Self {
-        self.uk = true;
+    pub fn set_reading_nokanji(&mut self) -> &mut Self {
+        if let Some(reading_nokanji) = self.reading_nokanji.last_mut() {
+            *reading_nokanji = true;
+        }
+        self
+    }
+    pub fn add_misc(&mut self, misc: String, sense: usize) -> &mut Self {
+        add_to_sense(&mut self.misc, misc, sense);
+        self
+    }
+    pub fn add_field(&mut self, field: String, sense: usize) -> &mut Self {
+        add_to_sense(&mut self.field, field, sense);
+        self
+    }
+    pub fn add_pos(&mut self, pos: String, sense: usize) -> &mut Self {
+        add_to_sense(&mut self.pos, pos, sense);
+        self
+    }
+    pub fn add_name_type(&mut self, name_type: String, sense: usize) -> &mut Self {
+        add_to_sense(&mut self.name_type, name_type, sense);
+        self
+    }
+    pub fn add_example(&mut self, example: Example, sense: usize) -> &mut Self {
+        add_to_sense(&mut self.examples, example, sense);
         self
     }
     pub fn sequence_number(&mut self, sequence_number: u32) -> &mut Self {
@@ -113,24 +626,12 @@ impl Definition {
         self
     }
     pub fn add_gloss(&mut self, gloss: String, sense: usize) -> &mut Self {
-        if self.gloss.is_empty() {
-            self.gloss = vec![vec![gloss]];
-        } else if self.gloss.len() == sense {
-            let mut sense = self.gloss.pop().unwrap();
-            sense.push(gloss);
-            self.gloss.push(sense);
-        } else if self.gloss.len() < sense {
-            let sense = vec![gloss];
-            self.gloss.push(sense);
-        }
+        add_to_sense(&mut self.gloss, gloss, sense);
         self
     }
     fn pos_to_identifier(&self) -> String {
         let mut ret = String::new();
-        if self.pos.is_empty() {
-            ret = "".to_string();
-        }
-        for i in &self.pos[0] {
+        for i in self.pos.first().into_iter().flatten() {
             match i.as_str() {
                 "v1" | "v1-s" => {
                     ret = "v1".to_string();
@@ -145,153 +646,555 @@ impl Definition {
         ret
     }
 
+    // the name types of every translation are the definition tags of a JMnedict row
+    fn definition_tags(&self) -> String {
+        let mut tags = self.name_type.concat();
+        tags.sort();
+        tags.dedup();
+        tags.join(" ")
+    }
+
+    // every kanji form with the readings that read it, then every reading on its own as a kana form
+    pub fn forms(&self) -> Vec<(&str, &str)> {
+        let mut forms = Vec::new();
+        for term in &self.term {
+            for (i, reading) in self.reading.iter().enumerate() {
+                if self.reads(i, term) {
+                    forms.push((term.as_str(), reading.as_str()));
+                }
+            }
+        }
+        for reading in &self.reading {
+            forms.push((reading.as_str(), reading.as_str()));
+        }
+        forms
+    }
+
+    /// Hides the forms whose status codes have the hide policy: they keep a row but leave the headers.
+    /// The forms with the drop policy are already gone, see `FormPolicies::drop_forms`.
+    pub fn hide_forms(&mut self, policies: &FormPolicies) -> &mut Self {
+        let forms = self.term.iter().zip(&self.term_info);
+        let readings = self.reading.iter().zip(&self.reading_info);
+        self.hidden_forms = forms
+            .chain(readings)
+            .filter(|(_, info)| policies.form_policy(*info) == Some(FormPolicy::Hide))
+            .map(|(form, _)| form.clone())
+            .collect();
+        self
+    }
+
+    // the status codes of a term[reading] row, shown as its term tags
+    fn status_tags(&self, term: Option<usize>, reading: usize) -> String {
+        let term_info = term.map(|j| &self.term_info[j]).into_iter().flatten();
+        let mut tags = term_info
+            .chain(&self.reading_info[reading])
+            .filter(|code| FORM_STATUS_CODES.contains(&code.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        tags.sort();
+        tags.dedup();
+        tags.join(" ")
+    }
+
+    // whether the reading at `reading` is a reading of `term`, following <re_restr> and <re_nokanji>
+    fn reads(&self, reading: usize, term: &str) -> bool {
+        if self.reading_nokanji.get(reading) == Some(&true) {
+            return false;
+        }
+        match self.reading_restriction.get(reading) {
+            Some(restriction) if !restriction.is_empty() => restriction.iter().any(|x| x == term),
+            _ => true,
+        }
+    }
+
+    // whether the reading at `reading` reads any of the kanji forms, a reading that reads none is a
+    // kana form of its own
+    fn reads_any_term(&self, reading: usize) -> bool {
+        self.term.iter().any(|term| self.reads(reading, term))
+    }
+
     pub fn increase_sense(&mut self) -> &mut Self {
         self.number_of_sense += 1;
         self
     }
 
-    pub fn serialize(&self, term_num: u16, vec_word_freq: &Vec<WordFrequency>) -> String {
-        let mut plus_count: u16 = 0;
-        let mut use_reading = false;
-        let no_kanji_term = false;
-        if self.reading.len() > self.term.len() {
-            use_reading = true;
+    // the popularity of this exact term[reading] if a frequency list has it,
+    // otherwise the entry popularity lowered by the position of the form
+    fn form_popularity(
+        &self,
+        term: &str,
+        reading: &str,
+        position: usize,
+        popularity: &Popularity,
+    ) -> f32 {
+        popularity
+            .get_form_popularity(self.sequence_number, term, reading)
+            .unwrap_or_else(|| popularity.get_popularity(self.sequence_number) - position as f32)
+    }
+
+    fn form_score(
+        &self,
+        term_index: usize,
+        reading_index: usize,
+        position: usize,
+        popularity: &Popularity,
+        weights: &ScoreWeights,
+    ) -> f32 {
+        weights.score(
+            self.form_popularity(
+                &self.term[term_index],
+                &self.reading[reading_index],
+                position,
+                popularity,
+            ),
+            self.term_priority[term_index]
+                .iter()
+                .chain(&self.reading_priority[reading_index]),
+            self.term_info[term_index]
+                .iter()
+                .chain(&self.reading_info[reading_index]),
+        )
+    }
+
+    // 1-based numbers of the senses marked `uk`
+    fn uk_senses(&self) -> Vec<usize> {
+        self.misc
+            .iter()
+            .enumerate()
+            .filter(|(_, misc)| misc.iter().any(|x| x == "uk"))
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    // the entry with only the given 1-based senses, in their order
+    fn with_senses(&self, senses: &[usize]) -> Definition {
+        fn pick<T: Clone + Default>(values: &[T], senses: &[usize]) -> Vec<T> {
+            senses
+                .iter()
+                .map(|&sense| values.get(sense - 1).cloned().unwrap_or_default())
+                .collect()
         }
-        let len = if use_reading {
-            self.reading.len()
-        } else {
-            self.term.len()
-        };
-        let mut ret = if term_num == 1 {
-            "[".to_string()
-        } else {
-            String::new()
-        };
+        // a sense without parts of speech has the ones of the previous sense
+        let mut pos = self.pos.clone();
+        pos.resize(pos.len().max(self.gloss.len()), Vec::new());
+        for i in 1..pos.len() {
+            if pos[i].is_empty() {
+                pos[i] = pos[i - 1].clone();
+            }
+        }
+        Definition {
+            pos: pick(&pos, senses),
+            gloss: pick(&self.gloss, senses),
+            misc: pick(&self.misc, senses),
+            field: pick(&self.field, senses),
+            name_type: pick(&self.name_type, senses),
+            examples: pick(&self.examples, senses),
+            number_of_sense: senses.len() as u16,
+            ..self.clone()
+        }
+    }
 
+    // a kana row scores like the reading of a kana only entry, and when every sense is usually
+    // written in kana it is the usual spelling, so it also scores as high as the best kanji row
+    fn kana_score(
+        &self,
+        reading_index: usize,
+        all_uk: bool,
+        best_kanji_score: Option<f32>,
+        popularity: &Popularity,
+        weights: &ScoreWeights,
+    ) -> f32 {
+        let score = weights.score(
+            self.form_popularity(&self.reading[reading_index], "", reading_index, popularity),
+            &self.reading_priority[reading_index],
+            &self.reading_info[reading_index],
+        );
+        match best_kanji_score {
+            Some(best) if all_uk => score.max(best),
+            _ => score,
+        }
+    }
+
+    pub fn serialize(
+        &self,
+        popularity: &Popularity,
+        weights: &ScoreWeights,
+        glossary_options: &GlossaryOptions,
+    ) -> Vec<String> {
+        let glossary = self.serialize_glossary(glossary_options);
+        let no_kanji_term = self.term.is_empty();
+        let mut best_kanji_score = None;
+        let mut ret = Vec::new();
+
+        // kana only entries are looked up by their readings
         if no_kanji_term {
-            for i in 0..len {
-                write!(
-                    ret,
-                    r#"["{}","{}","","{}",{},["{}"],{},""]"#,
-                    self.term[i],
-                    self.reading[0],
+            let mut scores = (0..self.reading.len())
+                .map(|i| {
+                    weights.score(
+                        self.form_popularity(&self.reading[i], "", i, popularity),
+                        &self.reading_priority[i],
+                        &self.reading_info[i],
+                    )
+                })
+                .collect::<Vec<f32>>();
+            let flagged = (0..self.reading.len())
+                .map(|i| !self.status_tags(None, i).is_empty())
+                .collect::<Vec<bool>>();
+            below_canonical(&mut scores, &flagged);
+            for (i, score) in scores.into_iter().enumerate() {
+                ret.push(format!(
+                    r#"[{},"","{}","{}",{},[{}],{},"{}"]"#,
+                    json_string(&self.reading[i]),
+                    self.definition_tags(),
+                    self.pos_to_identifier(),
+                    score,
+                    glossary,
+                    self.sequence_number,
+                    self.status_tags(None, i),
+                ));
+            }
+        } else {
+            // (term, reading, position of the form), the position follows the longer of the two lists
+            let (len_term, len_reading) = (self.term.len(), self.reading.len());
+            let pairs = if len_reading > len_term {
+                (0..len_reading)
+                    .flat_map(|i| (0..len_term).map(move |j| (j, i, i)))
+                    .collect::<Vec<(usize, usize, usize)>>()
+            } else {
+                (0..len_term)
+                    .flat_map(|j| (0..len_reading).map(move |i| (j, i, j)))
+                    .collect::<Vec<(usize, usize, usize)>>()
+            };
+            // a reading restricted to other kanji forms does not read this one
+            let pairs = pairs
+                .into_iter()
+                .filter(|&(j, i, _)| self.reads(i, &self.term[j]))
+                .collect::<Vec<(usize, usize, usize)>>();
+            let mut scores = pairs
+                .iter()
+                .map(|&(j, i, position)| self.form_score(j, i, position, popularity, weights))
+                .collect::<Vec<f32>>();
+            let flagged = pairs
+                .iter()
+                .map(|&(j, i, _)| !self.status_tags(Some(j), i).is_empty())
+                .collect::<Vec<bool>>();
+            below_canonical(&mut scores, &flagged);
+            best_kanji_score = scores.iter().copied().reduce(f32::max);
+            for (&(j, i, _), score) in pairs.iter().zip(scores) {
+                ret.push(format!(
+                    r#"[{},{},"{}","{}",{},[{}],{},"{}"]"#,
+                    json_string(&self.term[j]),
+                    json_string(&self.reading[i]),
+                    self.definition_tags(),
                     self.pos_to_identifier(),
-                    get_popularity(self.sequence_number, vec_word_freq) - i as f32,
-                    self.serialize_gloss(),
-                    term_num + plus_count,
-                )
-                .expect("Could not write to buffer string to serialize definitions");
-                if term_num < MAX_TERM_PER_BANK {
-                    ret = format!("{},\n", ret);
+                    score,
+                    glossary,
+                    self.sequence_number,
+                    self.status_tags(Some(j), i),
+                ));
+            }
+        }
+
+        // the readings of the senses usually written in kana are also looked up on their own, with
+        // the glossary of those senses only, and so is a reading of no kanji form, with every sense,
+        // kana only entries already got a row for each reading
+        if !no_kanji_term {
+            let uk_senses = self.uk_senses();
+            let all_uk = uk_senses.len() == self.gloss.len();
+            let uk_glossary = (!all_uk && !uk_senses.is_empty()).then(|| {
+                self.with_senses(&uk_senses)
+                    .serialize_glossary(glossary_options)
+            });
+            for i in 0..self.reading.len() {
+                let kana_glossary = if !self.reads_any_term(i) {
+                    &glossary
+                } else if !uk_senses.is_empty() {
+                    uk_glossary.as_ref().unwrap_or(&glossary)
                 } else {
-                    write!(ret, "]")
-                        .expect("Could not write to buffer string to serialize definitions");
-                }
+                    continue;
+                };
+                ret.push(format!(
+                    r#"[{},"","{}","{}",{},[{}],{},"{}"]"#,
+                    json_string(&self.reading[i]),
+                    self.definition_tags(),
+                    self.pos_to_identifier(),
+                    self.kana_score(i, all_uk, best_kanji_score, popularity, weights),
+                    kana_glossary,
+                    self.sequence_number,
+                    self.status_tags(None, i),
+                ));
+            }
+        }
+        ret
+    }
 
-                plus_count += 1;
-            }
-        } else if use_reading {
-            let len_term = self.term.len();
-            for i in 0..len {
-                for j in 0..len_term {
-                    write!(
-                        ret,
-                        r#"["{}","{}","","{}",{},["{}"],{},""]"#,
-                        self.term[j],
-                        self.reading[i],
-                        self.pos_to_identifier(),
-                        get_popularity(self.sequence_number, vec_word_freq) - i as f32,
-                        self.serialize_gloss(),
-                        term_num + plus_count,
-                    )
-                    .expect("Could not write to buffer string to serialize definitions");
-                    if term_num < MAX_TERM_PER_BANK {
-                        ret = format!("{},\n", ret);
-                    } else {
-                        write!(ret, "]")
-                            .expect("Could not write to buffer string to serialize definitions");
-                    }
+    //["遇う","あしらう","","",0,["配う"],1000300,""]
+    // rows of the forms dictionary: the other spellings and readings of each headword, with their
+    // status and priority codes, entries with a single form have none
+    pub fn serialize_forms(&self) -> Vec<String> {
+        // (kanji form, reading) of each headword, following <re_restr> and <re_nokanji>,
+        // a kana headword has no kanji form
+        let mut headwords = Vec::new();
+        for (j, term) in self.term.iter().enumerate() {
+            for i in 0..self.reading.len() {
+                if self.reads(i, term) {
+                    headwords.push((Some(j), i));
+                }
+            }
+        }
+        let all_kana = !self.uk_senses().is_empty() || self.term.is_empty();
+        for i in 0..self.reading.len() {
+            if all_kana || !self.reads_any_term(i) {
+                headwords.push((None, i));
+            }
+        }
 
-                    plus_count += 1;
+        let form = |text: &str, info: &[String], priority: &[String]| {
+            let mut ret = text.to_string();
+            if !info.is_empty() {
+                write!(ret, " 〘{}〙", info.join("・")).unwrap();
+            }
+            if !priority.is_empty() {
+                write!(ret, " ({})", priority.join(", ")).unwrap();
+            }
+            json_string(&ret)
+        };
+        let mut ret = Vec::new();
+        for (term, reading) in headwords {
+            let mut glossary = Vec::new();
+            for (j, other) in self.term.iter().enumerate() {
+                // a kana headword lists the spellings it reads
+                if Some(j) != term && (term.is_some() || self.reads(reading, other)) {
+                    glossary.push(form(other, &self.term_info[j], &self.term_priority[j]));
+                }
+            }
+            // and the other readings, only those of its spelling for a kanji headword
+            let term_text = term.map(|j| self.term[j].as_str());
+            for (i, other) in self.reading.iter().enumerate() {
+                if i != reading && term_text.is_none_or(|term| self.reads(i, term)) {
+                    glossary.push(form(
+                        other,
+                        &self.reading_info[i],
+                        &self.reading_priority[i],
+                    ));
                 }
             }
+            if glossary.is_empty() {
+                continue;
+            }
+            let (term, reading) = match term {
+                Some(j) => (self.term[j].as_str(), self.reading[reading].as_str()),
+                None => (self.reading[reading].as_str(), ""),
+            };
+            ret.push(format!(
+                r#"[{},{},"","",0,[{}],{},""]"#,
+                json_string(term),
+                json_string(reading),
+                glossary.join(","),
+                self.sequence_number,
+            ));
+        }
+        ret
+    }
+
+    //["明白","めいはく","","",708,["めいはく【明白】\n〘adj-na〙\nobvious; clear; plain; evident; apparent; explicit; overt."],26,""],
+    // the glossary item shared by every row of the entry
+    fn serialize_glossary(&self, options: &GlossaryOptions) -> String {
+        if options.structured_content {
+            self.structured_gloss(options).to_string()
         } else {
-            let len_reading = self.reading.len();
-            for i in 0..len {
-                for j in 0..len_reading {
-                    write!(
-                        ret,
-                        r#"["{}","{}","","{}",{},["{}"],{},""]"#,
-                        self.term[i],
-                        self.reading[j],
-                        self.pos_to_identifier(),
-                        get_popularity(self.sequence_number, vec_word_freq) - i as f32,
-                        self.serialize_gloss(),
-                        term_num + plus_count,
-                    )
-                    .expect("Could not write to buffer string to serialize definitions");
-                    if term_num < MAX_TERM_PER_BANK {
-                        ret = format!("{},\n", ret);
-                    } else {
-                        write!(ret, "]")
-                            .expect("Could not write to buffer string to serialize definitions");
-                    }
+            json_string(&self.serialize_gloss())
+        }
+    }
 
-                    plus_count += 1;
-                }
+    // あしらう【遇う・配う】
+    fn header(&self) -> String {
+        let terms = self.shown(&self.term);
+        let readings = self.shown(&self.reading);
+        if terms.is_empty() {
+            readings.join("・")
+        } else {
+            format!("{}【{}】", readings.join("・"), terms.join("・"))
+        }
+    }
+
+    // the forms that are not hidden, all of them if every one is hidden
+    fn shown<'a>(&self, forms: &'a [String]) -> Vec<&'a str> {
+        let shown = forms
+            .iter()
+            .filter(|form| !self.hidden_forms.contains(form))
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        if shown.is_empty() {
+            forms.iter().map(String::as_str).collect()
+        } else {
+            shown
+        }
+    }
+
+    // every kanji form with furigana of the first reading, then the other readings
+    fn ruby_header(&self, furigana: &Furigana) -> Value {
+        let terms = self.shown(&self.term);
+        let readings = self.shown(&self.reading);
+        let first_reading = match readings.first() {
+            Some(reading) if !terms.is_empty() => reading,
+            _ => return json!(self.header()),
+        };
+        let mut content = Vec::new();
+        for (i, term) in terms.iter().enumerate() {
+            if i > 0 {
+                content.push(json!("・"));
             }
+            content.push(furigana.ruby(term, first_reading));
         }
+        if readings.len() > 1 {
+            content.push(json!(format!("（{}）", readings[1..].join("・"))));
+        }
+        Value::Array(content)
+    }
 
-        // if uk then the len of term and reading is definitely > 0 so we don't have to check for it
-        if self.uk {
-            let len_reading = self.reading.len();
-            for i in 0..len_reading {
-                write!(
-                    ret,
-                    r#"["{}","","","{}",{},["{}"],{},""]"#,
-                    self.reading[i],
-                    self.pos_to_identifier(),
-                    get_popularity(self.sequence_number, vec_word_freq) + 1f32,
-                    self.serialize_gloss(),
-                    term_num + plus_count,
-                )
-                .expect("Could not write to buffer string to serialize definitions");
-                if term_num < MAX_TERM_PER_BANK {
-                    ret = format!("{},\n", ret);
-                } else {
-                    write!(ret, "]")
-                        .expect("Could not write to buffer string to serialize definitions");
-                }
+    // pos, misc and name types of a sense
+    fn sense_tags(&self, sense: usize) -> Vec<&str> {
+        [&self.pos, &self.misc, &self.name_type]
+            .iter()
+            .filter_map(|tags| tags.get(sense))
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
 
-                plus_count += 1;
+    // spellings across, readings down, a cell is marked when the reading reads the spelling
+    fn forms_table(&self) -> Value {
+        let form = |text: &str, info: &[String]| {
+            let mut content = vec![json!(text)];
+            for code in info {
+                let (marker, title) = form_status(code);
+                content.push(json!({
+                    "tag": "span",
+                    "data": {"content": "form-status"},
+                    "title": title,
+                    "content": format!(" {}", marker),
+                }));
             }
+            Value::Array(content)
+        };
+        let mut rows = Vec::new();
+        let mut header = vec![json!({"tag": "th", "content": ""})];
+        for (term, info) in self.term.iter().zip(&self.term_info) {
+            header.push(json!({"tag": "th", "content": form(term, info)}));
         }
-        ret
+        rows.push(json!({"tag": "tr", "content": header}));
+        for (i, (reading, info)) in self.reading.iter().zip(&self.reading_info).enumerate() {
+            let mut row = vec![json!({"tag": "th", "content": form(reading, info)})];
+            for term in &self.term {
+                let cell = if self.reads(i, term) { "○" } else { "" };
+                row.push(json!({"tag": "td", "content": cell}));
+            }
+            rows.push(json!({"tag": "tr", "content": row}));
+        }
+        json!({
+            "tag": "div",
+            "data": {"content": "forms"},
+            "content": {"tag": "table", "content": rows},
+        })
     }
 
-    //["明白","めいはく","","",708,["めいはく【明白】\n〘adj-na〙\nobvious; clear; plain; evident; apparent; explicit; overt."],26,""],
-    fn serialize_gloss(&self) -> String {
-        let mut ret = String::new();
-        write!(ret, "{}", self.reading.join("・")).unwrap();
-        write!(ret, "【{}】", self.term.join("・")).unwrap();
+    // the header, then one list item per sense with its examples under it
+    fn structured_gloss(&self, options: &GlossaryOptions) -> Value {
+        let senses = self
+            .gloss
+            .iter()
+            .enumerate()
+            .map(|(i, gloss)| {
+                let mut content = Vec::new();
+                let tags = self.sense_tags(i);
+                // the term used in an example only gets furigana when it is one of the kanji forms
+                let keyword = |text: &str| match self.reading.first() {
+                    Some(reading) if self.term.iter().any(|term| term == text) => {
+                        options.furigana.ruby(text, reading)
+                    }
+                    _ => json!(text),
+                };
+                if !tags.is_empty() {
+                    content.push(json!({
+                        "tag": "span",
+                        "data": {"content": "sense-tags"},
+                        "content": format!("〘{}〙 ", tags.join("・")),
+                    }));
+                }
+                content.push(json!(gloss.join("; ")));
+                let examples = self
+                    .examples
+                    .get(i)
+                    .into_iter()
+                    .flatten()
+                    .take(options.max_examples)
+                    .map(|example| {
+                        json!({
+                            "tag": "li",
+                            "content": [
+                                {"tag": "div", "lang": "ja", "content": example.highlighted_sentence(keyword(&example.text))},
+                                {"tag": "div", "lang": "en", "content": example.translation},
+                            ],
+                        })
+                    })
+                    .collect::<Vec<Value>>();
+                if !examples.is_empty() {
+                    content.push(json!({
+                        "tag": "ul",
+                        "data": {"content": "examples"},
+                        "content": examples,
+                    }));
+                }
+                json!({"tag": "li", "content": content})
+            })
+            .collect::<Vec<Value>>();
+
+        let mut content = vec![
+            json!({"tag": "div", "data": {"content": "header"}, "content": self.ruby_header(&options.furigana)}),
+            json!({"tag": "ol", "content": senses}),
+        ];
+        // a single spelling with a single reading has nothing to tabulate
+        if options.forms_table && !self.term.is_empty() && self.term.len() + self.reading.len() > 2
+        {
+            content.push(self.forms_table());
+        }
+        json!({"type": "structured-content", "content": content})
+    }
+
+    pub fn serialize_gloss(&self) -> String {
+        let mut ret = self.header();
+        // JMnedict translations have name types instead of parts of speech
+        if self.pos.is_empty() {
+            if self.gloss.len() == 1 {
+                write!(ret, "\n{}", self.gloss[0].join("; ")).unwrap();
+            } else {
+                for (i, gloss) in self.gloss.iter().enumerate() {
+                    write!(ret, "\n{} ", i + 1).unwrap();
+                    if let Some(name_type) = self.name_type.get(i).filter(|x| !x.is_empty()) {
+                        write!(ret, "〘{}〙 ", name_type.join("・")).unwrap();
+                    }
+                    write!(ret, "{}", gloss.join("; ")).unwrap();
+                }
+            }
+            return ret;
+        }
         if self.number_of_sense > 0 {
-            write!(ret, "\\n〘{}〙", self.pos[0].join("・")).unwrap();
+            write!(ret, "\n〘{}〙", self.pos[0].join("・")).unwrap();
         }
-        if self.pos.len() == 1 {
-            if !self.misc.is_empty() {
-                write!(ret, "\\n〘{}〙", self.misc[0].join("・")).unwrap();
+        if self.gloss.len() == 1 {
+            if self.misc.first().is_some_and(|misc| !misc.is_empty()) {
+                write!(ret, "\n〘{}〙", self.misc[0].join("・")).unwrap();
             }
-            write!(ret, "\\n{}.", self.gloss[0].join("; ")).unwrap();
+            write!(ret, "\n{}.", self.gloss[0].join("; ")).unwrap();
         } else {
-            for (i, _val) in self.pos.iter().enumerate() {
-                write!(
-                    ret,
-                    "\\n{} 〘{}〙 {}.",
-                    i + 1,
-                    self.misc[i].join("・"),
-                    self.gloss[i].join("; ")
-                )
-                .unwrap();
+            for i in 0..self.gloss.len() {
+                write!(ret, "\n{} ", i + 1).unwrap();
+                // a sense without misc codes gets no empty brackets
+                if let Some(misc) = self.misc.get(i).filter(|misc| !misc.is_empty()) {
+                    write!(ret, "〘{}〙 ", misc.join("・")).unwrap();
+                }
+                write!(ret, "{}.", self.gloss[i].join("; ")).unwrap();
             }
         }
         ret
@@ -301,14 +1204,27 @@ impl Definition {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::entry::{Form, Sense};
     use crate::word_frequency::parser::parse_frequency_input;
+    use crate::word_frequency::stats::{MissingPopularity, Normalization};
+
+    fn sample_word_freq() -> Vec<WordFrequency> {
+        let raw_freq_input = std::fs::read_to_string("tests/frequency-sample.txt").unwrap();
+        let (_, vec_word_freq) = parse_frequency_input(raw_freq_input.as_bytes()).unwrap();
+        vec_word_freq
+    }
+
+    fn sample_popularity() -> Popularity {
+        Popularity::new(
+            &sample_word_freq(),
+            Normalization::Raw,
+            MissingPopularity::MeanMinusStdDev,
+        )
+    }
 
     #[test]
-    #[ignore]
     fn serialize_single_term() {
-        let raw_freq_input =
-            std::fs::read_to_string("japanese-word-frequency/frequency.txt").unwrap();
-        let (_, vec_word_freq) = parse_frequency_input(&raw_freq_input.as_bytes()).unwrap();
+        let popularity = sample_popularity();
 
         let mut definitions = Definition::default();
         definitions.sequence_number(1000220u32);
@@ -322,22 +1238,25 @@ mod tests {
         definitions.add_gloss("apparent".to_string(), 1);
         definitions.add_gloss("explicit".to_string(), 1);
         definitions.add_gloss("overt".to_string(), 1);
+        definitions.increase_sense();
 
-        let serialized = r#"["明白","めいはく","","",98,["めいはく【明白】\n〘adj-na〙\nobvious; clear; plain; evident; apparent; explicit; overt."],26,""],"#.to_string();
-        let serialized = format!("{}\n", serialized);
-        assert_eq!(definitions.serialize(26, &vec_word_freq), serialized);
+        let serialized = r#"["明白","めいはく","","",98,["めいはく【明白】\n〘adj-na〙\nobvious; clear; plain; evident; apparent; explicit; overt."],1000220,""]"#.to_string();
+        assert_eq!(
+            definitions.serialize(
+                &popularity,
+                &ScoreWeights::default(),
+                &GlossaryOptions::default()
+            ),
+            vec![serialized]
+        );
     }
 
     #[test]
-    #[ignore]
     fn serialize_multiple_term_uk() {
-        let raw_freq_input =
-            std::fs::read_to_string("japanese-word-frequency/frequency.txt").unwrap();
-        let (_, vec_word_freq) = parse_frequency_input(&raw_freq_input.as_bytes()).unwrap();
+        let popularity = sample_popularity();
 
         let mut definitions = Definition::default();
         definitions.sequence_number(1000300u32);
-        definitions.set_uk();
         definitions.add_term("遇う".to_string());
         definitions.add_term("配う".to_string());
         definitions.add_reading("あしらう".to_string());
@@ -347,6 +1266,7 @@ mod tests {
         definitions.add_gloss("to treat".to_string(), 1);
         definitions.add_gloss("to handle".to_string(), 1);
         definitions.add_gloss("to deal with".to_string(), 1);
+        definitions.increase_sense();
         definitions.add_pos("v5u".to_string(), 2);
         definitions.add_pos("vt".to_string(), 2);
         definitions.add_misc("uk".to_string(), 2);
@@ -354,14 +1274,127 @@ mod tests {
         definitions.add_gloss("to decorate".to_string(), 2);
         definitions.add_gloss("to dress".to_string(), 2);
         definitions.add_gloss("to garnish".to_string(), 2);
+        definitions.increase_sense();
+
+        let serialized_1 = r#"["遇う","あしらう","","v5",52,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],1000300,""]"#.to_string();
+        let serialized_2 = r#"["配う","あしらう","","v5",36,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],1000300,""]"#.to_string();
+        // every sense is uk, so the kana row scores as high as the best kanji row
+        let serialized_3 = r#"["あしらう","","","v5",52,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],1000300,""]"#.to_string();
+        assert_eq!(
+            definitions.serialize(
+                &popularity,
+                &ScoreWeights::default(),
+                &GlossaryOptions::default()
+            ),
+            vec![serialized_1, serialized_2, serialized_3]
+        );
+    }
+
+    #[test]
+    fn serialize_partial_uk() {
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Mean);
 
-        let serialized_1 = r#"["遇う","あしらう","","v5",52,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],35,""],"#.to_string();
-        let serialized = format!("{}", serialized_1);
-        let serialized_2 = r#"["配う","あしらう","","v5",51,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],36,""],"#.to_string();
-        let serialized = format!("{}\n{}", serialized, serialized_2);
-        let serialized_3 = r#"["あしらう","","","v5",53,["あしらう【遇う・配う】\n〘v5u・vt〙\n1 〘uk〙 to treat; to handle; to deal with.\n2 〘uk〙 to arrange; to decorate; to dress; to garnish."],37,""],"#.to_string();
-        let serialized = format!("{}\n{}\n", serialized, serialized_3);
-        assert_eq!(definitions.serialize(35, &vec_word_freq), serialized);
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1000310u32);
+        definitions.add_term("馬酔木".to_string());
+        definitions.add_reading("あせび".to_string());
+        definitions.add_reading("あしび".to_string());
+        definitions.add_pos("n".to_string(), 1);
+        definitions.add_gloss("Japanese andromeda".to_string(), 1);
+        definitions.increase_sense();
+        definitions.add_misc("uk".to_string(), 2);
+        definitions.add_gloss("lily-of-the-valley bush".to_string(), 2);
+        definitions.increase_sense();
+
+        let serialized = definitions.serialize(
+            &popularity,
+            &ScoreWeights::default(),
+            &GlossaryOptions::default(),
+        );
+        assert_eq!(serialized.len(), 4);
+        assert!(serialized[0].contains(
+            r#"["あせび・あしび【馬酔木】\n〘n〙\n1 Japanese andromeda.\n2 〘uk〙 lily-of-the-valley bush."]"#
+        ));
+        // the kana rows only have the uk sense and score like their own kana form
+        assert_eq!(
+            serialized[2],
+            r#"["あせび","","","",0,["あせび・あしび【馬酔木】\n〘n〙\n〘uk〙\nlily-of-the-valley bush."],1000310,""]"#
+        );
+        assert!(serialized[3].starts_with(r#"["あしび","","","",-1,"#));
+    }
+
+    #[test]
+    fn serialize_priority_and_status() {
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Mean);
+
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1580640u32);
+        definitions.add_term("人".to_string());
+        definitions.add_term_priority("news1".to_string());
+        definitions.add_term("仁".to_string());
+        definitions.add_term_info("iK".to_string());
+        definitions.add_reading("ひと".to_string());
+        definitions.add_reading_priority("news1".to_string());
+        definitions.add_pos("n".to_string(), 1);
+        definitions.add_gloss("person".to_string(), 1);
+        definitions.increase_sense();
+
+        let serialized = definitions.serialize(
+            &popularity,
+            &ScoreWeights::default(),
+            &GlossaryOptions::default(),
+        );
+        assert!(serialized[0].starts_with(r#"["人","ひと","","",10,"#));
+        // the news1 of the reading still applies to the irregular kanji form
+        assert!(serialized[1].starts_with(r#"["仁","ひと","","",-11,"#));
+    }
+
+    #[test]
+    fn serialize_kana_only_term() {
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Mean);
+
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1000320u32);
+        definitions.add_reading("ああ".to_string());
+        definitions.add_reading_priority("ichi1".to_string());
+        definitions.add_reading("アー".to_string());
+        definitions.add_pos("adv".to_string(), 1);
+        definitions.add_gloss("like that".to_string(), 1);
+        definitions.add_gloss("so".to_string(), 1);
+        definitions.increase_sense();
+
+        // a row for each reading, scored like a term of its own
+        assert_eq!(
+            definitions.serialize(
+                &popularity,
+                &ScoreWeights::default(),
+                &GlossaryOptions::default()
+            ),
+            vec![
+                r#"["ああ","","","",10,["ああ・アー\n〘adv〙\nlike that; so."],1000320,""]"#,
+                r#"["アー","","","",-1,["ああ・アー\n〘adv〙\nlike that; so."],1000320,""]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn sense_tags_stay_on_their_sense() {
+        let mut definitions = Definition::default();
+        definitions.add_term("馬酔木".to_string());
+        definitions.add_reading("あせび".to_string());
+        definitions.add_pos("n".to_string(), 1);
+        definitions.add_gloss("Japanese andromeda".to_string(), 1);
+        definitions.increase_sense();
+        // the first sense has no <misc>, the second no <pos>
+        definitions.add_misc("uk".to_string(), 2);
+        definitions.add_gloss("lily-of-the-valley bush".to_string(), 2);
+        definitions.increase_sense();
+
+        assert_eq!(definitions.sense_tags(0), ["n"]);
+        assert_eq!(definitions.sense_tags(1), ["uk"]);
+        assert!(definitions
+            .serialize_gloss()
+            .ends_with("\n2 〘uk〙 lily-of-the-valley bush."));
     }
 
     #[test]
@@ -371,10 +1404,310 @@ mod tests {
             format: 3u8,
             revision: "JMdict1".to_string(),
             sequenced: true,
+            author: None,
+            url: None,
+            description: None,
+            attribution: None,
+            source_language: None,
+            target_language: None,
+            frequency_mode: None,
+            is_updatable: None,
+            index_url: None,
+            download_url: None,
         };
         assert_eq!(
             dict_index.serialize(),
             r#"{"title":"JMdict","format":3,"revision":"JMdict1","sequenced":true}"#
         );
     }
+
+    #[test]
+    fn serialize_frequency_meta() {
+        let frequency_meta = FrequencyMeta::from_word_frequency(&sample_word_freq());
+        assert_eq!(
+            frequency_meta[0].serialize("rank-based"),
+            r#"["明白","freq",{"reading":"めいはく","frequency":{"value":1,"displayValue":"1 (98)"}}]"#
+        );
+        // 36.9 is shared by two forms
+        assert_eq!(frequency_meta[1].rank, 3);
+        assert_eq!(frequency_meta[4].rank, 3);
+        assert_eq!(frequency_meta[5].rank, 5);
+        assert_eq!(
+            frequency_meta[8].serialize("rank-based"),
+            r#"["アセビ","freq",{"value":8,"displayValue":"8 (36.5)"}]"#
+        );
+        assert_eq!(
+            frequency_meta[8].serialize("occurrence-based"),
+            r#"["アセビ","freq",{"value":36.5,"displayValue":"8 (36.5)"}]"#
+        );
+    }
+
+    #[test]
+    fn frequency_meta_shared_form() {
+        let mut vec_word_freq = sample_word_freq();
+        vec_word_freq.push(WordFrequency {
+            ent_seq: 2000000,
+            popularity: 40f32,
+            term: "馬酔木".to_string(),
+            reading: "あしび".to_string(),
+        });
+        let frequency_meta = FrequencyMeta::from_word_frequency(&vec_word_freq);
+        // 馬酔木[あしび] of the second entry adds no row, it only raises the first one
+        assert_eq!(frequency_meta.len(), 9);
+        assert_eq!(
+            frequency_meta[5].serialize("rank-based"),
+            r#"["馬酔木","freq",{"reading":"あしび","frequency":{"value":3,"displayValue":"3 (40)"}}]"#
+        );
+        assert_eq!(frequency_meta[1].rank, 4);
+    }
+
+    #[test]
+    fn serialize_dict_index_metadata() {
+        let metadata: IndexMetadata = serde_json::from_str(
+            r#"{"title":"JMdict (common)","author":"EDRDG","targetLanguage":"en-GB"}"#,
+        )
+        .unwrap();
+        let mut dict_index = DictIndex::new("JMdict", "20220207");
+        dict_index.metadata(&metadata);
+        dict_index.attribution = None;
+        assert_eq!(
+            dict_index.serialize(),
+            r#"{"title":"JMdict (common)","format":3,"revision":"JMdict-20220207","sequenced":true,"author":"EDRDG","sourceLanguage":"ja","targetLanguage":"en-GB"}"#
+        );
+        assert!(serde_json::from_str::<IndexMetadata>(r#"{"tilte":"JMdict"}"#).is_err());
+    }
+
+    #[test]
+    fn serialize_updatable_dict_index() {
+        let mut dict_index = DictIndex::new("JMdict", "20220207");
+        dict_index.updatable("https://dict.example.org/yomichan/", "JMdict");
+        assert!(dict_index.serialize().ends_with(
+            r#""isUpdatable":true,"indexUrl":"https://dict.example.org/yomichan/JMdict/index.json","downloadUrl":"https://dict.example.org/yomichan/JMdict/JMdict.zip"}"#
+        ));
+    }
+
+    #[test]
+    fn serialize_form_policies() {
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Mean);
+        let mut policies = FormPolicies::default();
+        policies.set("oK=drop").unwrap();
+
+        let form = |text: &str, info: &[&str], restrictions: &[&str]| Form {
+            info: info.iter().map(|x| x.to_string()).collect(),
+            restrictions: restrictions.iter().map(|x| x.to_string()).collect(),
+            ..Form::new(text.to_string())
+        };
+        let mut entry = Entry {
+            ent_seq: 1580640,
+            kanji: vec![
+                form("人", &[], &[]),
+                form("仁", &["iK"], &[]),
+                form("𠆢", &["sK"], &[]),
+                form("亻", &["oK"], &[]),
+            ],
+            readings: vec![form("ひと", &[], &["亻", "人"]), form("じん", &[], &[])],
+            senses: vec![Sense {
+                gloss: vec!["person".to_string()],
+                ..Sense::default()
+            }],
+        };
+        policies.drop_forms(&mut entry);
+        let mut definitions = Definition::from(&entry);
+        definitions.hide_forms(&policies);
+
+        let serialized = definitions.serialize(
+            &popularity,
+            &ScoreWeights::default(),
+            &GlossaryOptions::default(),
+        );
+        // the outdated form is dropped, the search-only form keeps its row but leaves the header,
+        // and ひと, now only restricted to 人, reads neither 仁 nor 𠆢
+        assert_eq!(serialized.len(), 4);
+        assert!(serialized[0].starts_with(r#"["人","ひと","","",0,["ひと・じん【人・仁】"#));
+        assert!(serialized[1].starts_with(r#"["人","じん","","",0,"#));
+        assert!(serialized[2].starts_with(r#"["仁","じん","","",-21,"#));
+        assert!(serialized[2].ends_with(r#",1580640,"iK"]"#));
+        assert!(serialized[3].starts_with(r#"["𠆢","じん","","",-52,"#));
+        assert!(serialized[3].ends_with(r#",1580640,"sK"]"#));
+        assert_eq!(definitions.reading_restriction, [vec!["人"], vec![]]);
+    }
+
+    #[test]
+    fn serialize_follows_restrictions() {
+        let popularity = Popularity::new(&[], Normalization::Raw, MissingPopularity::Mean);
+
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1000310u32);
+        definitions.add_term("馬酔木".to_string());
+        definitions.add_term("梫".to_string());
+        definitions.add_term_info("rK".to_string());
+        definitions.add_term_info("iK".to_string());
+        definitions.add_reading("あせび".to_string());
+        definitions.add_reading("あしび".to_string());
+        definitions.add_reading_restriction("馬酔木".to_string());
+        definitions.add_reading("アセビ".to_string());
+        definitions.add_reading_info("rK".to_string());
+        definitions.set_reading_nokanji();
+        definitions.add_gloss("Japanese andromeda".to_string(), 1);
+        definitions.increase_sense();
+
+        let rows = definitions
+            .serialize(
+                &popularity,
+                &ScoreWeights::default(),
+                &GlossaryOptions::default(),
+            )
+            .iter()
+            .map(|row| row.split(",\"\",").next().unwrap().to_string())
+            .collect::<Vec<String>>();
+        // アセビ reads no kanji form, it is looked up on its own
+        assert_eq!(
+            rows,
+            [
+                r#"["馬酔木","あせび""#,
+                r#"["梫","あせび""#,
+                r#"["馬酔木","あしび""#,
+                r#"["アセビ""#,
+            ]
+        );
+        // a code of both the spelling and the reading is shown once
+        assert_eq!(definitions.status_tags(Some(1), 2), "iK rK");
+    }
+
+    #[test]
+    fn status_forms_below_canonical() {
+        let mut scores = [10f32, 30f32, -5f32, 20f32];
+        below_canonical(&mut scores, &[false, true, true, false]);
+        assert_eq!(scores, [10f32, 9f32, -5f32, 20f32]);
+        // without a canonical form the scores stay as they are
+        let mut scores = [10f32, 30f32];
+        below_canonical(&mut scores, &[true, true]);
+        assert_eq!(scores, [10f32, 30f32]);
+    }
+
+    #[test]
+    fn serialize_forms_companion() {
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1580640u32);
+        definitions.add_term("人".to_string());
+        definitions.add_term_priority("news1".to_string());
+        definitions.add_term_priority("ichi1".to_string());
+        definitions.add_term("仁".to_string());
+        definitions.add_term_info("iK".to_string());
+        definitions.add_reading("ひと".to_string());
+        definitions.add_reading_priority("news1".to_string());
+
+        assert_eq!(
+            definitions.serialize_forms(),
+            [
+                r#"["人","ひと","","",0,["仁 〘iK〙"],1580640,""]"#,
+                r#"["仁","ひと","","",0,["人 (news1, ichi1)"],1580640,""]"#,
+            ]
+        );
+
+        let mut single = Definition::default();
+        single.add_term("明白".to_string());
+        single.add_reading("めいはく".to_string());
+        assert!(single.serialize_forms().is_empty());
+    }
+
+    #[test]
+    fn serialize_forms_follow_restrictions() {
+        let mut definitions = Definition::default();
+        definitions.sequence_number(1000310u32);
+        definitions.add_term("馬酔木".to_string());
+        definitions.add_term("梫".to_string());
+        definitions.add_term_info("rK".to_string());
+        definitions.add_reading("あせび".to_string());
+        definitions.add_reading("あしび".to_string());
+        definitions.add_reading_restriction("馬酔木".to_string());
+        definitions.add_reading("アセビ".to_string());
+        definitions.set_reading_nokanji();
+
+        // アセビ reads no kanji form, so it is a kana headword of its own
+        assert_eq!(
+            definitions.serialize_forms(),
+            [
+                r#"["馬酔木","あせび","","",0,["梫 〘rK〙","あしび"],1000310,""]"#,
+                r#"["馬酔木","あしび","","",0,["梫 〘rK〙","あせび"],1000310,""]"#,
+                r#"["梫","あせび","","",0,["馬酔木"],1000310,""]"#,
+                r#"["アセビ","","","",0,["あせび","あしび"],1000310,""]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn structured_forms_table() {
+        let mut definitions = Definition::default();
+        definitions.add_term("馬酔木".to_string());
+        definitions.add_term("梫".to_string());
+        definitions.add_term_info("rK".to_string());
+        definitions.add_reading("あせび".to_string());
+        definitions.add_reading("あしび".to_string());
+        definitions.add_reading_restriction("馬酔木".to_string());
+        definitions.add_reading("アセビ".to_string());
+        definitions.set_reading_nokanji();
+        definitions.add_gloss("Japanese andromeda".to_string(), 1);
+        definitions.increase_sense();
+
+        let glossary_options = GlossaryOptions {
+            structured_content: true,
+            forms_table: true,
+            ..GlossaryOptions::default()
+        };
+        let content = definitions.structured_gloss(&glossary_options);
+        let status = json!({"tag": "span", "data": {"content": "form-status"}, "title": "rarely used kanji form", "content": " rare"});
+        assert_eq!(
+            content["content"][2]["content"]["content"],
+            json!([
+                {"tag": "tr", "content": [
+                    {"tag": "th", "content": ""},
+                    {"tag": "th", "content": ["馬酔木"]},
+                    {"tag": "th", "content": ["梫", status]},
+                ]},
+                {"tag": "tr", "content": [
+                    {"tag": "th", "content": ["あせび"]},
+                    {"tag": "td", "content": "○"},
+                    {"tag": "td", "content": "○"},
+                ]},
+                {"tag": "tr", "content": [
+                    {"tag": "th", "content": ["あしび"]},
+                    {"tag": "td", "content": "○"},
+                    {"tag": "td", "content": ""},
+                ]},
+                {"tag": "tr", "content": [
+                    {"tag": "th", "content": ["アセビ"]},
+                    {"tag": "td", "content": ""},
+                    {"tag": "td", "content": ""},
+                ]},
+            ])
+        );
+        // no table without the option
+        let content = definitions.structured_gloss(&GlossaryOptions::default());
+        assert_eq!(content["content"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn revision_date_sources() {
+        assert_eq!(
+            revision_date(Some("2022-02-07".to_string()), Some("0".to_string())).unwrap(),
+            "20220207"
+        );
+        assert_eq!(
+            revision_date(None, Some("1644192000".to_string())).unwrap(),
+            "20220207"
+        );
+        assert!(revision_date(None, Some("yesterday".to_string())).is_err());
+        assert!(revision_date(Some("2022".to_string()), None).is_err());
+        assert!(revision_date(Some("2022-02-30".to_string()), None).is_err());
+    }
+
+    #[test]
+    fn separate_revision_date() {
+        assert_eq!(
+            separated_revision_date("20220207", '.').unwrap(),
+            "2022.02.07"
+        );
+        assert!(separated_revision_date("2022", '-').is_err());
+    }
 }